@@ -0,0 +1,88 @@
+use crate::cli::ParamsOverrides;
+use crate::cli::Projection;
+use crate::cli::SimulationFrame;
+use crate::params::Params;
+use crate::simulate::Tracer;
+use anyhow::Result;
+use indicatif::ProgressBar;
+use rumpus::prelude::*;
+use std::{path::PathBuf, time::Instant};
+
+/// Timing of repeated simulations in seconds.
+#[derive(serde::Serialize)]
+struct BenchReport {
+    iterations: u32,
+    pixels: usize,
+    threads: usize,
+    mean_s: f64,
+    median_s: f64,
+    min_s: f64,
+    max_s: f64,
+    pixels_per_s: f64,
+}
+
+pub fn run(
+    params: &Option<PathBuf>,
+    overrides: &ParamsOverrides,
+    iterations: u32,
+    json: bool,
+    projection: &Projection,
+) -> Result<()> {
+    let params = Params::load(params, overrides)?;
+    let image_sensor = params.image_sensor();
+    let camera = Camera::new(params.lens()?, params.orientation());
+    let progress = ProgressBar::hidden();
+    let pixels = usize::from(params.image_rows()) * usize::from(params.image_cols());
+
+    // Time the sky model and the trace, which is everything simulate does
+    // besides writing the outputs.
+    let mut times = Vec::new();
+    for _ in 0..iterations {
+        let start = Instant::now();
+        let sky_model = params.sky_model()?;
+        let tracer = Tracer {
+            params: &params,
+            image_sensor: &image_sensor,
+            camera: &camera,
+            sky_model: &sky_model,
+            frame: &SimulationFrame::Meridian,
+            projection,
+            mask: None,
+        };
+        tracer.trace(0..params.image_rows(), &progress)?;
+        times.push(start.elapsed().as_secs_f64());
+    }
+
+    times.sort_by(f64::total_cmp);
+    let mean_s = times.iter().sum::<f64>() / times.len() as f64;
+    let median_s = match times.len() % 2 {
+        0 => (times[times.len() / 2 - 1] + times[times.len() / 2]) / 2.,
+        _ => times[times.len() / 2],
+    };
+    let report = BenchReport {
+        iterations,
+        pixels,
+        threads: rayon::current_num_threads(),
+        mean_s,
+        median_s,
+        min_s: times[0],
+        max_s: times[times.len() - 1],
+        pixels_per_s: pixels as f64 / mean_s,
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    println!("iterations:  {}", report.iterations);
+    println!("pixels:      {}", report.pixels);
+    println!("threads:     {}", report.threads);
+    println!("mean:        {:.4} s", report.mean_s);
+    println!("median:      {:.4} s", report.median_s);
+    println!("min:         {:.4} s", report.min_s);
+    println!("max:         {:.4} s", report.max_s);
+    println!("throughput:  {:.0} pixels/s", report.pixels_per_s);
+
+    Ok(())
+}
@@ -1,39 +1,523 @@
+use chrono::TimeDelta;
+use chrono::prelude::*;
+use clap::Args;
 use clap::Parser;
 use clap::Subcommand;
 use clap::ValueEnum;
+use rumpus::prelude::StokesReferenceFrame;
 use std::path::PathBuf;
 
 #[derive(Parser)]
 pub struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    /// Maximum number of threads used for parallel work.
+    ///
+    /// If zero or not provided, all available cores are used.
+    #[arg(long, global = true, default_value_t = 0)]
+    threads: usize,
+
+    /// Log progress to stderr, repeat for more detail.
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
 }
 
-#[derive(Subcommand, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Subcommand, Clone, PartialEq, PartialOrd)]
 #[non_exhaustive]
 pub enum Commands {
     /// Generate a simulation of the skylight polarization pattern.
-    Simulate {
-        /// Optional path to TOML formatted SensorParams.
+    Simulate(SimulateArgs),
+
+    /// Report the solar azimuth and elevation for the configured position and time.
+    SunPosition {
+        /// Optional path to TOML, JSON, or YAML formatted SensorParams.
         ///
-        /// If not provided, the default SensorParams is used.
+        /// If not provided, the user config at `rumpus_cli/config.toml` in the
+        /// platform config directory is used, falling back to the default SensorParams.
         #[arg(short, long)]
         params: Option<PathBuf>,
 
-        /// Simulation target.
-        #[arg(short, long, value_enum)]
-        target: SimulationTarget,
+        #[command(flatten)]
+        overrides: ParamsOverrides,
+    },
+
+    /// Check that a SensorParams file parses and describes a valid simulation.
+    Validate {
+        /// Path to TOML, JSON, or YAML formatted SensorParams.
+        params: PathBuf,
+    },
 
-        /// File path for the simulated output.
+    /// Time repeated simulations without writing any output.
+    Bench {
+        /// Optional path to TOML, JSON, or YAML formatted SensorParams.
+        #[arg(short, long)]
+        params: Option<PathBuf>,
+
+        #[command(flatten)]
+        overrides: ParamsOverrides,
+
+        /// Number of simulations to time.
+        #[arg(
+            short = 'n',
+            long,
+            default_value_t = 10,
+            value_parser = clap::value_parser!(u32).range(1..),
+        )]
+        iterations: u32,
+
+        /// Print the results as JSON instead of a table.
+        #[arg(long)]
+        json: bool,
+
+        /// Mapping from the sensor radius to the angle of the bearing off the optical axis.
+        #[arg(long, value_enum, default_value_t = Projection::Rectilinear)]
+        projection: Projection,
+    },
+
+    /// Compare two AoP arrays, reporting the RMSE and writing an error heatmap.
+    Compare {
+        /// Reference `.dat` or `.npy` AoP array in degrees.
+        reference: PathBuf,
+
+        /// Candidate `.dat` or `.npy` AoP array in degrees.
+        candidate: PathBuf,
+
+        /// File path for the PNG heatmap of the absolute angular error.
         #[arg(short, long)]
         output: PathBuf,
 
-        /// Format for simulation output.
-        ///
-        /// If not provided, the output format is inferred from the file extension.
-        #[arg(short, long, value_enum)]
-        format: Option<SimulationFormat>,
+        /// Colormap used to render the heatmap over [0, 90] degrees.
+        #[arg(long, value_enum, default_value_t = Colormap::Jet)]
+        colormap: Colormap,
     },
+
+    /// Compute AoP and DoP images from a polarized intensity image.
+    Process(ProcessArgs),
+}
+
+#[derive(Args, Clone, PartialEq, PartialOrd)]
+pub struct ProcessArgs {
+    /// Path to the input intensity image, or `-` to read it from stdin.
+    ///
+    /// A directory processes every supported image inside it.
+    pub image: PathBuf,
+
+    /// Encoding of the input image.
+    ///
+    /// Required when reading from stdin, otherwise inferred from the file.
+    #[arg(long, value_enum)]
+    pub input_format: Option<InputFormat>,
+
+    /// File path for the AoP image.
+    #[arg(long, default_value = "aop.png")]
+    pub aop_output: PathBuf,
+
+    /// File path for the DoP image.
+    #[arg(long, default_value = "dop.png")]
+    pub dop_output: PathBuf,
+
+    /// Directory for `<name>_aop.png` and `<name>_dop.png` when the input is a directory.
+    #[arg(long)]
+    pub output_dir: Option<PathBuf>,
+
+    /// Warn about and skip images that fail when processing a directory.
+    #[arg(long)]
+    pub continue_on_error: bool,
+
+    /// Fail instead of replacing outputs that already exist.
+    #[arg(long)]
+    pub no_clobber: bool,
+
+    /// Reference frame for the AoP.
+    #[arg(long, value_enum, default_value_t = ReferenceFrame::Pixel)]
+    pub frame: ReferenceFrame,
+
+    /// DoP at the top of the colormap, larger values are clamped to it.
+    #[arg(long, default_value_t = 0.5)]
+    pub dop_max: f64,
+
+    /// Colormap used to render the DoP image.
+    #[arg(long, value_enum, default_value_t = Colormap::Jet)]
+    pub colormap: Colormap,
+}
+
+#[derive(Args, Clone, PartialEq, PartialOrd)]
+pub struct SimulateArgs {
+    /// Optional path to TOML, JSON, or YAML formatted SensorParams.
+    ///
+    /// The format is inferred from the file extension.
+    /// If not provided, the user config at `rumpus_cli/config.toml` in the
+    /// platform config directory is used, falling back to the default SensorParams.
+    #[arg(short, long)]
+    pub params: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub overrides: ParamsOverrides,
+
+    /// Simulation target.
+    #[arg(short, long, value_enum)]
+    pub target: SimulationTarget,
+
+    /// File path for the simulated output, or `-` to write to stdout.
+    ///
+    /// Writing to stdout requires --format and a single output, so it cannot be
+    /// combined with --stokes, --metadata, a time series, or a sweep. Only
+    /// optional alongside --info, in which case nothing is simulated.
+    #[arg(short, long, required_unless_present = "info")]
+    pub output: Option<PathBuf>,
+
+    /// Format for simulation output.
+    ///
+    /// If not provided, the output format is inferred from the file extension.
+    #[arg(short, long, value_enum)]
+    pub format: Option<SimulationFormat>,
+
+    /// Optional file path for a DoP output written alongside the target.
+    ///
+    /// The format is inferred from the file extension.
+    #[arg(long)]
+    pub dop_output: Option<PathBuf>,
+
+    /// Optional file path for the scattering angle, between the bearing of each
+    /// pixel and the sun, written alongside the target.
+    ///
+    /// The format is inferred from the file extension.
+    #[arg(long)]
+    pub scattering_angle: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub output_options: OutputOptions,
+
+    /// Start of a time series, rendering one numbered frame per time step.
+    ///
+    /// Overrides the time in SensorParams.
+    #[arg(long, requires = "time_end")]
+    pub time_start: Option<DateTime<Utc>>,
+
+    /// End of a time series, inclusive.
+    #[arg(long, requires = "time_start")]
+    pub time_end: Option<DateTime<Utc>>,
+
+    /// Interval between frames of a time series, e.g. `90s`, `15m`, or `1h`.
+    #[arg(long, requires = "time_start", value_parser = parse_time_step)]
+    pub time_step: Option<TimeDelta>,
+
+    /// Sweep the camera yaw in degrees as `start:end:step`, writing one output per angle.
+    #[arg(long, value_parser = parse_sweep)]
+    pub yaw_sweep: Option<Sweep>,
+
+    /// Sweep the camera pitch in degrees as `start:end:step`.
+    #[arg(long, value_parser = parse_sweep)]
+    pub pitch_sweep: Option<Sweep>,
+
+    /// Sweep the camera roll in degrees as `start:end:step`.
+    #[arg(long, value_parser = parse_sweep)]
+    pub roll_sweep: Option<Sweep>,
+
+    /// Write a JSON sidecar with the resolved params next to each output.
+    ///
+    /// The sidecar shares the output's file stem, e.g. `out.json` for `out.png`.
+    #[arg(long)]
+    pub metadata: bool,
+
+    /// Reference frame for the simulated AoP.
+    #[arg(long, value_enum, default_value_t = SimulationFrame::Meridian)]
+    pub frame: SimulationFrame,
+
+    /// Standard deviation in degrees of Gaussian noise added to each AoP.
+    #[arg(long)]
+    pub noise_std_deg: Option<f64>,
+
+    /// Standard deviation of Gaussian noise added to each DoP.
+    #[arg(long)]
+    pub noise_dop_std: Option<f64>,
+
+    /// Seed for the noise generator, making noisy runs reproducible.
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Also write the normalized Stokes Q and U next to the output.
+    ///
+    /// They use the output format and are named with `_q` and `_u` suffixes.
+    #[arg(long)]
+    pub stokes: bool,
+
+    /// Print the field of view, sensor size, and sun elevation before simulating.
+    #[arg(long)]
+    pub info: bool,
+
+    /// Suppress the progress bar and the populated pixel summary.
+    #[arg(short, long)]
+    pub quiet: bool,
+
+    /// Mapping from the sensor radius to the angle of the bearing off the optical axis.
+    #[arg(long, value_enum, default_value_t = Projection::Rectilinear)]
+    pub projection: Projection,
+
+    /// Grayscale image the size of the sensor where zero pixels are not traced.
+    ///
+    /// Masked pixels are written as missing. With --bin, the mask must match
+    /// the binned size.
+    #[arg(long)]
+    pub mask: Option<PathBuf>,
+
+    /// Rotate or flip the simulated image before it is written.
+    ///
+    /// Only the pixels are rearranged, the simulated values are unchanged.
+    #[arg(long, value_enum)]
+    pub transform: Option<Transform>,
+
+    /// Trace and write the sensor N rows at a time to bound memory use.
+    ///
+    /// Only supported for dat and csv outputs.
+    #[arg(
+        long,
+        value_parser = clap::value_parser!(u16).range(1..),
+        conflicts_with_all = ["transform", "dop_output", "scattering_angle", "stokes"],
+    )]
+    pub tile_rows: Option<u16>,
+
+    /// Trace one ray per N x N block of sensor pixels for faster previews.
+    ///
+    /// Outputs are written at the reduced resolution. Rows and columns that
+    /// do not fill a whole block are dropped.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    pub bin: u16,
+}
+
+// Parse a whole number of seconds, minutes, or hours; bare numbers are seconds.
+fn parse_time_step(s: &str) -> Result<TimeDelta, String> {
+    let (value, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => s.split_at(i),
+        None => (s, "s"),
+    };
+
+    let value: i64 = value
+        .parse()
+        .map_err(|_| format!("expected a time step like 15m, got {s}"))?;
+    match unit {
+        "s" => TimeDelta::try_seconds(value),
+        "m" => TimeDelta::try_minutes(value),
+        "h" => TimeDelta::try_hours(value),
+        _ => {
+            return Err(format!(
+                "unknown time step unit {unit}, expected s, m, or h"
+            ));
+        }
+    }
+    .ok_or_else(|| format!("time step {s} is out of range"))
+}
+
+/// An inclusive range of evenly spaced values.
+#[derive(Copy, Clone, PartialEq, PartialOrd)]
+pub struct Sweep {
+    start: f64,
+    end: f64,
+    step: f64,
+    /// Decimal places of the start and step as given.
+    decimals: i32,
+}
+
+impl Sweep {
+    pub fn values(&self) -> Vec<f64> {
+        // Tolerate rounding so an end that lies on a step is included.
+        let steps = ((self.end - self.start) / self.step + 1e-9).floor() as usize;
+        // Round away the accumulated error so values like 0.3 print cleanly.
+        let scale = 10f64.powi(self.decimals);
+        (0..=steps)
+            .map(|i| ((self.start + i as f64 * self.step) * scale).round() / scale)
+            .collect()
+    }
+}
+
+// Decimal places written in a number, with exponents allowing for more.
+fn decimals(s: &str) -> i32 {
+    if s.contains(['e', 'E']) {
+        return 12;
+    }
+
+    s.trim()
+        .split_once('.')
+        .map(|(_, fraction)| fraction.len() as i32)
+        .unwrap_or(0)
+}
+
+fn parse_sweep(s: &str) -> Result<Sweep, String> {
+    let values: Vec<f64> = s
+        .split(':')
+        .map(|value| value.trim().parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("expected start:end:step, got {s}"))?;
+
+    let [start, end, step] = values[..] else {
+        return Err(format!("expected start:end:step, got {s}"));
+    };
+    if end < start {
+        return Err(format!("sweep end {end} is before start {start}"));
+    }
+    if step <= 0. {
+        return Err(format!("sweep step {step} must be positive"));
+    }
+
+    let parts: Vec<&str> = s.split(':').collect();
+    let decimals = decimals(parts[0]).max(decimals(parts[2]));
+
+    Ok(Sweep {
+        start,
+        end,
+        step,
+        decimals,
+    })
+}
+
+/// Individual SensorParams fields that take precedence over the params file.
+#[derive(Args, Clone, Default, PartialEq, PartialOrd)]
+pub struct ParamsOverrides {
+    /// Override the camera yaw in degrees.
+    #[arg(long)]
+    pub yaw_deg: Option<f64>,
+
+    /// Override the camera pitch in degrees.
+    #[arg(long)]
+    pub pitch_deg: Option<f64>,
+
+    /// Override the camera roll in degrees.
+    #[arg(long)]
+    pub roll_deg: Option<f64>,
+
+    /// Override the observer latitude in degrees.
+    #[arg(long)]
+    pub lat_deg: Option<f64>,
+
+    /// Override the observer longitude in degrees.
+    #[arg(long)]
+    pub lon_deg: Option<f64>,
+
+    /// Override the observer altitude in meters.
+    #[arg(long)]
+    pub alt_m: Option<f64>,
+
+    /// Override the time of the simulation.
+    ///
+    /// Either RFC3339 with an offset, or a local time like `2025-06-13 12:26:47`
+    /// followed by or combined with --timezone.
+    #[arg(long)]
+    pub time: Option<String>,
+
+    /// IANA timezone like `America/Toronto`, or an offset like `-04:00`, of a local --time.
+    #[arg(long, requires = "time")]
+    pub timezone: Option<String>,
+
+    /// Override the focal length of the lens in millimeters.
+    #[arg(long)]
+    pub focal_length_mm: Option<f64>,
+
+    /// Override both pixel dimensions of the sensor in micrometers.
+    #[arg(long)]
+    pub pixel_size_um: Option<f64>,
+
+    /// Override the pixel width of the sensor in micrometers.
+    #[arg(long)]
+    pub pixel_width_um: Option<f64>,
+
+    /// Override the pixel height of the sensor in micrometers.
+    #[arg(long)]
+    pub pixel_height_um: Option<f64>,
+
+    /// Override the model of the skylight polarization pattern.
+    #[arg(long, value_enum)]
+    pub sky_model: Option<SkyModelKind>,
+}
+
+/// Settings shared by the simulation output writers.
+#[derive(Args, Clone, PartialEq, PartialOrd)]
+pub struct OutputOptions {
+    /// Colormap used to render image outputs.
+    #[arg(long, value_enum, default_value_t = Colormap::Jet)]
+    pub colormap: Colormap,
+
+    /// Unit of the AoP in numeric outputs and of the colormap bounds.
+    #[arg(long, value_enum, default_value_t = AngleUnit::Degrees)]
+    pub angle_unit: AngleUnit,
+
+    /// Bit depth of PNG outputs.
+    #[arg(long, value_enum, default_value_t = BitDepth::Eight)]
+    pub bit_depth: BitDepth,
+
+    /// Lower bound in degrees of the colormap span for AoP image outputs.
+    ///
+    /// Pixels outside the span are drawn with the missing color.
+    #[arg(long, allow_hyphen_values = true, default_value_t = -90.)]
+    pub aop_min: f64,
+
+    /// Upper bound in degrees of the colormap span for AoP image outputs.
+    #[arg(long, allow_hyphen_values = true, default_value_t = 90.)]
+    pub aop_max: f64,
+
+    /// Draw a labelled colorbar on the right side of PNG outputs.
+    #[arg(long)]
+    pub legend: bool,
+
+    /// Color for pixels without a simulated value in image outputs.
+    ///
+    /// Accepts a hex code like `#000000` or a named color like `black`.
+    #[arg(long, value_parser = parse_color, default_value = "white")]
+    pub missing_color: [u8; 3],
+
+    /// Sentinel for pixels without a simulated value in numeric outputs.
+    ///
+    /// If not provided, missing pixels are written as NaN.
+    #[arg(long)]
+    pub missing_value: Option<f64>,
+
+    /// Fail instead of replacing outputs that already exist.
+    #[arg(long)]
+    pub no_clobber: bool,
+
+    /// Write dat outputs as raw little-endian f64 with an ENVI `.hdr` header
+    /// instead of ASCII, for loading in ENVI or GDAL.
+    #[arg(long)]
+    pub envi: bool,
+
+    /// Write a header row and column of pixel indices in CSV outputs.
+    #[arg(long)]
+    pub header: bool,
+
+    /// Comma separated quantities written as adjacent columns for each pixel
+    /// in dat and csv outputs, e.g. `aop,dop`.
+    ///
+    /// If not provided, only the target is written.
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub channels: Vec<SimulationTarget>,
+}
+
+fn parse_color(s: &str) -> Result<[u8; 3], String> {
+    let named = match s.to_ascii_lowercase().as_str() {
+        "white" => Some([255, 255, 255]),
+        "black" => Some([0, 0, 0]),
+        "gray" | "grey" => Some([128, 128, 128]),
+        "red" => Some([255, 0, 0]),
+        "green" => Some([0, 255, 0]),
+        "blue" => Some([0, 0, 255]),
+        "cyan" => Some([0, 255, 255]),
+        "magenta" => Some([255, 0, 255]),
+        "yellow" => Some([255, 255, 0]),
+        _ => None,
+    };
+    if let Some(color) = named {
+        return Ok(color);
+    }
+
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    if hex.len() != 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(format!("expected a color like #rrggbb or a name, got {s}"));
+    }
+
+    let channel = |i: usize| {
+        u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| format!("invalid hex color {s}"))
+    };
+    Ok([channel(0)?, channel(2)?, channel(4)?])
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
@@ -41,6 +525,20 @@ pub enum Commands {
 pub enum SimulationFormat {
     Png,
     Dat,
+    Csv,
+    /// FITS file with the target in the primary HDU, followed by the DoP in
+    /// an extension when the target is the AoP.
+    ///
+    /// Rows are stored top sensor row first, like the other formats.
+    Fits,
+    /// NumPy binary array of shape (rows, cols).
+    Npy,
+    /// Single-band float TIFF geotagged with the observer position and time.
+    ///
+    /// Only a tiepoint at the first pixel is embedded, not a pixel scale, so
+    /// GIS tools locate the observer but report no geotransform.
+    #[value(name = "geotiff")]
+    GeoTiff,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
@@ -48,10 +546,173 @@ pub enum SimulationFormat {
 pub enum SimulationTarget {
     Aop,
     Dop,
+    /// Normalized Stokes Q, dop * cos(2 * aop).
+    Q,
+    /// Normalized Stokes U, dop * sin(2 * aop).
+    U,
+    /// Angle between the bearing of each pixel and the sun.
+    ScatteringAngle,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+#[non_exhaustive]
+pub enum Colormap {
+    Jet,
+    Viridis,
+    Grayscale,
+    /// Cyclic hue colormap suited to angular data like AoP.
+    Hsv,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+#[non_exhaustive]
+pub enum AngleUnit {
+    Degrees,
+    Radians,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+#[non_exhaustive]
+pub enum BitDepth {
+    /// RGB rendered through the colormap.
+    #[value(name = "8")]
+    Eight,
+    /// Grayscale scaled linearly from the target minimum at 1 to maximum at 65535.
+    ///
+    /// The scale is recorded in `min` and `max` text chunks and missing
+    /// pixels are written as zero, so the minimum stays distinct from them.
+    #[value(name = "16")]
+    Sixteen,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+#[non_exhaustive]
+pub enum SimulationFrame {
+    /// AoP as produced by the sky model.
+    Meridian,
+    /// AoP measured from the direction pointing away from the image center.
+    Pixel,
+}
+
+#[derive(
+    Copy,
+    Clone,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    ValueEnum,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum SkyModelKind {
+    /// Single-scattering Rayleigh sky.
+    #[default]
+    Rayleigh,
+}
+
+#[derive(
+    Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum Projection {
+    /// Pinhole lens where the radius is f * tan(theta).
+    Rectilinear,
+    /// Fisheye lens where the radius is f * theta.
+    FisheyeEquidistant,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+#[non_exhaustive]
+pub enum Transform {
+    /// Rotate clockwise by 90 degrees.
+    Rotate90,
+    Rotate180,
+    /// Rotate clockwise by 270 degrees.
+    Rotate270,
+    /// Mirror left to right.
+    FlipH,
+    /// Mirror top to bottom.
+    FlipV,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+#[non_exhaustive]
+pub enum ReferenceFrame {
+    Sensor,
+    Pixel,
+}
+
+impl From<&ReferenceFrame> for StokesReferenceFrame {
+    fn from(frame: &ReferenceFrame) -> Self {
+        match frame {
+            ReferenceFrame::Sensor => StokesReferenceFrame::Sensor,
+            ReferenceFrame::Pixel => StokesReferenceFrame::Pixel,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+#[non_exhaustive]
+pub enum InputFormat {
+    Png,
+    Tiff,
+    Bmp,
+    Pnm,
+}
+
+impl From<&InputFormat> for image::ImageFormat {
+    fn from(format: &InputFormat) -> Self {
+        match format {
+            InputFormat::Png => image::ImageFormat::Png,
+            InputFormat::Tiff => image::ImageFormat::Tiff,
+            InputFormat::Bmp => image::ImageFormat::Bmp,
+            InputFormat::Pnm => image::ImageFormat::Pnm,
+        }
+    }
 }
 
 impl Cli {
     pub fn command(&self) -> Commands {
         self.command.clone()
     }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    pub fn verbose(&self) -> u8 {
+        self.verbose
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sweep_values_are_rounded_to_the_given_precision() {
+        let sweep = parse_sweep("0:1:0.1").unwrap();
+        let values = sweep.values();
+        assert_eq!(values.len(), 11);
+        assert_eq!(values[3], 0.3);
+        assert_eq!(format!("{}", values[3]), "0.3");
+        assert_eq!(values[10], 1.);
+    }
+
+    #[test]
+    fn parse_color_rejects_signed_hex() {
+        assert_eq!(parse_color("#ff8000"), Ok([255, 128, 0]));
+        assert!(parse_color("#+f+f+f").is_err());
+    }
+
+    #[test]
+    fn sweep_with_whole_steps_keeps_integers() {
+        let sweep = parse_sweep("-10:10:5").unwrap();
+        assert_eq!(sweep.values(), vec![-10., -5., 0., 5., 10.]);
+    }
 }
@@ -0,0 +1,204 @@
+use crate::cli::Colormap;
+use crate::simulate::{to_rgb, wrap_aop};
+use anyhow::Context;
+use anyhow::Result;
+use std::{ffi::OsStr, fs, path::PathBuf};
+
+/// Row-major array of AoP values read from a simulation output.
+pub(crate) struct AopArray {
+    pub(crate) rows: usize,
+    pub(crate) cols: usize,
+    pub(crate) values: Vec<f64>,
+}
+
+pub fn run(
+    reference: &PathBuf,
+    candidate: &PathBuf,
+    output: &PathBuf,
+    colormap: &Colormap,
+) -> Result<()> {
+    let reference = read_array(reference)?;
+    let candidate = read_array(candidate)?;
+    if (reference.rows, reference.cols) != (candidate.rows, candidate.cols) {
+        anyhow::bail!(
+            "reference is {}x{} but candidate is {}x{}",
+            reference.rows,
+            reference.cols,
+            candidate.rows,
+            candidate.cols
+        );
+    }
+
+    // Both arrays must agree on which pixels are missing.
+    let misaligned: Vec<usize> = (0..reference.values.len())
+        .filter(|&i| reference.values[i].is_nan() != candidate.values[i].is_nan())
+        .collect();
+    if let Some(&first) = misaligned.first() {
+        anyhow::bail!(
+            "missing pixels differ at {} pixels, first at row {} col {}",
+            misaligned.len(),
+            first / reference.cols,
+            first % reference.cols
+        );
+    }
+
+    // The AoP is cyclic over 180 degrees, so 89 and -89 are 2 degrees apart.
+    let errors: Vec<Option<f64>> = reference
+        .values
+        .iter()
+        .zip(&candidate.values)
+        .map(|(r, c)| match r.is_nan() {
+            true => None,
+            false => Some(wrap_aop(c - r).abs()),
+        })
+        .collect();
+
+    let valid: Vec<f64> = errors.iter().flatten().copied().collect();
+    if valid.is_empty() {
+        anyhow::bail!("no valid pixels to compare");
+    }
+    let rmse = (valid.iter().map(|e| e * e).sum::<f64>() / valid.len() as f64).sqrt();
+    let max = valid.iter().copied().fold(0., f64::max);
+
+    println!("valid pixels: {}", valid.len());
+    println!("rmse:         {rmse:.4} deg");
+    println!("max error:    {max:.4} deg");
+
+    // Draw the error heatmap, with missing pixels as white.
+    let image: Vec<u8> = errors
+        .iter()
+        .flat_map(|error| match error {
+            Some(error) => to_rgb(*error, 0., 90., colormap).unwrap_or([255, 255, 255]),
+            None => [255, 255, 255],
+        })
+        .collect();
+    image::save_buffer(
+        output,
+        &image,
+        reference.cols.try_into()?,
+        reference.rows.try_into()?,
+        image::ExtendedColorType::Rgb8,
+    )?;
+
+    Ok(())
+}
+
+fn read_array(path: &PathBuf) -> Result<AopArray> {
+    let array = match path.extension().map(|os_str: &OsStr| os_str.to_str()) {
+        Some(Some("dat")) => read_dat(path),
+        Some(Some("npy")) => read_npy(path),
+        _ => anyhow::bail!("expected a .dat or .npy file, got {}", path.display()),
+    };
+
+    array.with_context(|| format!("read {}", path.display()))
+}
+
+// Whitespace separated values with one image row per line.
+// Comment lines starting with `#` are skipped.
+fn read_dat(path: &PathBuf) -> Result<AopArray> {
+    let text = fs::read_to_string(path)?;
+
+    let mut cols = None;
+    let mut values = Vec::new();
+    let mut rows = 0;
+    for (i, line) in text
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+        .enumerate()
+    {
+        let row: Vec<f64> = line
+            .split_whitespace()
+            .map(|value| value.parse())
+            .collect::<Result<_, _>>()
+            .with_context(|| format!("invalid value on line {}", i + 1))?;
+
+        if *cols.get_or_insert(row.len()) != row.len() {
+            anyhow::bail!(
+                "line {} has {} values, expected {:?}",
+                i + 1,
+                row.len(),
+                cols
+            );
+        }
+        values.extend(row);
+        rows += 1;
+    }
+
+    Ok(AopArray {
+        rows,
+        cols: cols.unwrap_or(0),
+        values,
+    })
+}
+
+// Two dimensional, C-ordered, little-endian f64 arrays as written by simulate.
+pub(crate) fn read_npy(path: &PathBuf) -> Result<AopArray> {
+    let bytes = fs::read(path)?;
+
+    let magic = b"\x93NUMPY";
+    if !bytes.starts_with(magic) || bytes.len() < magic.len() + 2 {
+        anyhow::bail!("missing NumPy magic string");
+    }
+    let (header_len, header_start) = match bytes[magic.len()] {
+        1 => {
+            let len = bytes.get(8..10).context("truncated header length")?;
+            (u16::from_le_bytes(len.try_into()?) as usize, 10)
+        }
+        2 | 3 => {
+            let len = bytes.get(8..12).context("truncated header length")?;
+            (u32::from_le_bytes(len.try_into()?) as usize, 12)
+        }
+        version => anyhow::bail!("unsupported NumPy format version {version}"),
+    };
+
+    let data_start = header_start + header_len;
+    let header = std::str::from_utf8(
+        bytes
+            .get(header_start..data_start)
+            .context("truncated header")?,
+    )?;
+    if !header.contains("'descr': '<f8'") || !header.contains("'fortran_order': False") {
+        anyhow::bail!("expected a C-ordered array of little-endian f64");
+    }
+
+    let shape = header
+        .split_once("'shape': (")
+        .and_then(|(_, rest)| rest.split_once(')'))
+        .map(|(shape, _)| shape)
+        .context("missing array shape")?;
+    let dims: Vec<usize> = shape
+        .split(',')
+        .map(str::trim)
+        .filter(|dim| !dim.is_empty())
+        .map(str::parse)
+        .collect::<Result<_, _>>()?;
+    let [rows, cols] = dims[..] else {
+        anyhow::bail!("expected a two dimensional array, got shape ({shape})");
+    };
+
+    let values: Vec<f64> = bytes[data_start..]
+        .chunks_exact(size_of::<f64>())
+        .map(|chunk| chunk.try_into().map(f64::from_le_bytes))
+        .collect::<Result<_, _>>()?;
+    if values.len() != rows * cols {
+        anyhow::bail!("expected {} values, found {}", rows * cols, values.len());
+    }
+
+    Ok(AopArray { rows, cols, values })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_npy_rejects_a_truncated_header_length() {
+        let path =
+            std::env::temp_dir().join(format!("rumpus_cli_{}_truncated.npy", std::process::id()));
+        for version in [1u8, 2] {
+            fs::write(&path, [b"\x93NUMPY".as_slice(), &[version, 0, 0]].concat()).unwrap();
+            assert!(read_npy(&path).is_err());
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
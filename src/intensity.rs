@@ -1,51 +1,141 @@
-use clap::Parser;
-use image::ImageReader;
+use crate::cli::InputFormat;
+use crate::cli::ProcessArgs;
+use crate::simulate::{check_clobber, to_rgb};
+use anyhow::Context;
+use anyhow::Result;
+use image::{DynamicImage, ImageFormat, ImageReader};
+use rayon::prelude::*;
 use rumpus::image::IntensityImage;
-use std::path::PathBuf;
+use rumpus::prelude::*;
+use std::{
+    fs,
+    io::{self, Cursor, Read},
+    path::PathBuf,
+};
 
-#[derive(Parser)]
-#[command(version, about, long_about = None)]
-struct Args {
-    /// Path to the input image.
-    image: PathBuf,
+pub fn run(args: &ProcessArgs) -> Result<()> {
+    if !(args.dop_max > 0. && args.dop_max <= 1.) {
+        anyhow::bail!("dop-max {} must be in (0, 1]", args.dop_max);
+    }
 
-    #[arg(long, default_value_t = 0.5)]
-    dop_max: f64,
-}
+    if !args.image.is_dir() {
+        return process(&args.image, &args.aop_output, &args.dop_output, args);
+    }
+
+    let output_dir = args
+        .output_dir
+        .as_ref()
+        .context("--output-dir is required when the input is a directory")?;
+    fs::create_dir_all(output_dir).with_context(|| format!("create {}", output_dir.display()))?;
+
+    // Only pick up files the image crate knows how to decode.
+    let mut images: Vec<PathBuf> = fs::read_dir(&args.image)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?
+        .into_iter()
+        .filter(|path| path.is_file() && ImageFormat::from_path(path).is_ok())
+        .collect();
+    images.sort();
 
-fn main() {
-    let args = Args::parse();
+    let process_one = |image: &PathBuf| {
+        let stem = image
+            .file_stem()
+            .map(|stem| stem.to_string_lossy())
+            .unwrap_or_default();
+        process(
+            image,
+            &output_dir.join(format!("{stem}_aop.png")),
+            &output_dir.join(format!("{stem}_dop.png")),
+            args,
+        )
+        .with_context(|| format!("process {}", image.display()))
+    };
 
-    let image = ImageReader::open(args.image)
-        .unwrap()
-        .decode()
-        .unwrap()
-        .into_luma8();
+    if !args.continue_on_error {
+        return images.par_iter().try_for_each(process_one);
+    }
+
+    let failed = images
+        .par_iter()
+        .map(process_one)
+        .filter_map(Result::err)
+        .inspect(|err| tracing::warn!("skipping: {err:#}"))
+        .count();
+    if failed > 0 {
+        tracing::warn!("{failed} of {} images failed", images.len());
+    }
+
+    Ok(())
+}
+
+fn process(
+    image: &PathBuf,
+    aop_output: &PathBuf,
+    dop_output: &PathBuf,
+    args: &ProcessArgs,
+) -> Result<()> {
+    for output in [aop_output, dop_output] {
+        check_clobber(output, args.no_clobber)?;
+    }
+    let image = read_image(image, &args.input_format)?.into_luma8();
 
     let (width, height) = image.dimensions();
     let stokes_image = IntensityImage::from_bytes(width, height, &image.into_raw())
-        .unwrap()
+        .context("intensity image with whole super-pixels")?
         .into_stokes_image()
-        .par_transform_frame(StokesReferenceFrame::Pixel);
+        .par_transform_frame((&args.frame).into());
 
-    let mms = stokes_image.into_measurements();
     let (width, height) = stokes_image.dimensions();
+    let mms = stokes_image.into_measurements();
     let aop_image = AopImage::from_sparse_mms(&mms, width, height).into_raw();
-    let dop_image = DopImage::from_sparse_mms(&mms, width, height).into_raw();
 
-    let _ = image::save_buffer(
-        "aop.png",
+    // Render the DoP of the same measurements, clamping values above dop_max.
+    // Pixels without a measurement are left missing.
+    let mut dop_image = vec![MISSING; width as usize * height as usize];
+    for mm in &mms {
+        let pixel = mm.pixel();
+        let dop = mm.dop().into_inner().min(args.dop_max);
+        dop_image[pixel.row() as usize * width as usize + pixel.col() as usize] =
+            to_rgb(dop, 0., args.dop_max, &args.colormap).unwrap_or(MISSING);
+    }
+
+    image::save_buffer(
+        &aop_output,
         &aop_image,
         width,
         height,
         image::ExtendedColorType::Rgb8,
-    );
+    )?;
 
-    let _ = image::save_buffer(
-        "dop.png",
-        &dop_image,
+    image::save_buffer(
+        &dop_output,
+        &dop_image.concat(),
         width,
         height,
         image::ExtendedColorType::Rgb8,
-    );
+    )?;
+
+    Ok(())
+}
+
+const MISSING: [u8; 3] = [255, 255, 255];
+
+// Decode the intensity image from a file, or from stdin when the path is `-`.
+fn read_image(path: &PathBuf, format: &Option<InputFormat>) -> Result<DynamicImage> {
+    if path.as_os_str() == "-" {
+        let format = format.context("--input-format is required when reading from stdin")?;
+
+        // The decoders need to seek, so buffer the whole of stdin first.
+        let mut bytes = Vec::new();
+        io::stdin().lock().read_to_end(&mut bytes)?;
+        let reader = ImageReader::with_format(Cursor::new(bytes), (&format).into());
+        return reader.decode().context("decode image from stdin");
+    }
+
+    let mut reader = ImageReader::open(path).with_context(|| format!("open {}", path.display()))?;
+    if let Some(format) = format {
+        reader.set_format(format.into());
+    }
+
+    Ok(reader.decode()?)
 }
@@ -0,0 +1,155 @@
+use crate::cli::Colormap;
+use crate::simulate::to_rgb;
+
+// Horizontal layout of the legend strip in unscaled pixels.
+const MARGIN: u32 = 8;
+const BAR_WIDTH: u32 = 16;
+const TICK_LENGTH: u32 = 4;
+const LABEL_CHARS: u32 = 5;
+
+// Glyphs are drawn from a 5x7 bitmap font at an integer scale.
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+const GLYPH_SCALE: u32 = 2;
+
+const BACKGROUND: [u8; 3] = [255, 255, 255];
+const FOREGROUND: [u8; 3] = [0, 0, 0];
+
+/// Width in pixels that [`with_legend`] adds to the right side of an image.
+pub fn legend_width() -> u32 {
+    MARGIN
+        + BAR_WIDTH
+        + TICK_LENGTH
+        + MARGIN / 2
+        + LABEL_CHARS * (GLYPH_WIDTH + 1) * GLYPH_SCALE
+        + MARGIN
+}
+
+/// Append a vertical colorbar for `[x_min, x_max]` to an RGB buffer.
+///
+/// The bar runs from `x_max` at the top to `x_min` at the bottom and is
+/// labelled at the minimum, midpoint, and maximum. Returns a buffer that is
+/// [`legend_width`] pixels wider than the input.
+pub fn with_legend(
+    image: &[u8],
+    rows: u32,
+    cols: u32,
+    x_min: f64,
+    x_max: f64,
+    colormap: &Colormap,
+) -> Vec<u8> {
+    let width = cols + legend_width();
+    let mut canvas = Canvas {
+        pixels: vec![0; (width * rows * 3) as usize],
+        width,
+        rows,
+    };
+
+    for row in 0..rows {
+        let start = (row * cols * 3) as usize;
+        let end = start + (cols * 3) as usize;
+        canvas.row_mut(row)[..(cols * 3) as usize].copy_from_slice(&image[start..end]);
+        for col in cols..width {
+            canvas.set(row, col, BACKGROUND);
+        }
+    }
+
+    // Keep sufficient space above and below the bar for the end labels.
+    let label_height = GLYPH_HEIGHT * GLYPH_SCALE;
+    let top = MARGIN.max(label_height / 2).min(rows.saturating_sub(1));
+    let bottom = rows.saturating_sub(top + 1).max(top);
+    let bar_left = cols + MARGIN;
+    let span = (bottom - top).max(1);
+
+    for row in top..=bottom {
+        let x = x_max - f64::from(row - top) / f64::from(span) * (x_max - x_min);
+        let color = to_rgb(x.clamp(x_min, x_max), x_min, x_max, colormap).unwrap_or(BACKGROUND);
+        for col in bar_left..bar_left + BAR_WIDTH {
+            canvas.set(row, col, color);
+        }
+    }
+
+    let ticks = [
+        (top, x_max),
+        ((top + bottom) / 2, (x_min + x_max) / 2.),
+        (bottom, x_min),
+    ];
+    for (row, value) in ticks {
+        let tick_left = bar_left + BAR_WIDTH;
+        for col in tick_left..tick_left + TICK_LENGTH {
+            canvas.set(row, col, FOREGROUND);
+        }
+
+        let label_top = row.saturating_sub(label_height / 2);
+        canvas.draw_text(
+            label_top,
+            tick_left + TICK_LENGTH + MARGIN / 2,
+            &format!("{value:.1}"),
+        );
+    }
+
+    canvas.pixels
+}
+
+struct Canvas {
+    pixels: Vec<u8>,
+    width: u32,
+    rows: u32,
+}
+
+impl Canvas {
+    fn row_mut(&mut self, row: u32) -> &mut [u8] {
+        let start = (row * self.width * 3) as usize;
+        &mut self.pixels[start..start + (self.width * 3) as usize]
+    }
+
+    // Pixels outside the canvas are silently dropped.
+    fn set(&mut self, row: u32, col: u32, color: [u8; 3]) {
+        if row < self.rows && col < self.width {
+            let i = ((row * self.width + col) * 3) as usize;
+            self.pixels[i..i + 3].copy_from_slice(&color);
+        }
+    }
+
+    fn draw_text(&mut self, top: u32, left: u32, text: &str) {
+        for (i, c) in (0..).zip(text.chars()) {
+            let glyph_left = left + i * (GLYPH_WIDTH + 1) * GLYPH_SCALE;
+            for (glyph_row, bits) in (0..).zip(glyph(c)) {
+                for glyph_col in 0..GLYPH_WIDTH {
+                    if bits & (1 << (GLYPH_WIDTH - 1 - glyph_col)) == 0 {
+                        continue;
+                    }
+
+                    for dy in 0..GLYPH_SCALE {
+                        for dx in 0..GLYPH_SCALE {
+                            self.set(
+                                top + glyph_row * GLYPH_SCALE + dy,
+                                glyph_left + glyph_col * GLYPH_SCALE + dx,
+                                FOREGROUND,
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Rows of a 5x7 glyph, most significant of the low five bits on the left.
+fn glyph(c: char) -> [u8; 7] {
+    match c {
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        '-' => [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C],
+        _ => [0x00; 7],
+    }
+}
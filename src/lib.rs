@@ -1,2 +1,9 @@
+pub mod bench;
 pub mod cli;
+pub mod compare;
+pub mod intensity;
+mod legend;
+mod params;
 pub mod simulate;
+pub mod sun;
+pub mod validate;
@@ -3,17 +3,46 @@ use anyhow::bail;
 use clap::Parser;
 use rumpus_cli::cli::Cli;
 use rumpus_cli::cli::Commands;
+use tracing::Level;
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
 
+    // Warnings are always logged, each -v adds a level of detail.
+    let level = match cli.verbose() {
+        0 => Level::WARN,
+        1 => Level::INFO,
+        2 => Level::DEBUG,
+        _ => Level::TRACE,
+    };
+    tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(std::io::stderr)
+        .init();
+
+    // Every par_iter runs on the global pool, so size it before dispatching.
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(cli.threads())
+        .build_global()?;
+
     match &cli.command() {
-        Commands::Simulate {
+        Commands::Simulate(args) => rumpus_cli::simulate::run(args),
+        Commands::SunPosition { params, overrides } => rumpus_cli::sun::run(params, overrides),
+        Commands::Validate { params } => rumpus_cli::validate::run(params),
+        Commands::Bench {
             params,
-            target,
+            overrides,
+            iterations,
+            json,
+            projection,
+        } => rumpus_cli::bench::run(params, overrides, *iterations, *json, projection),
+        Commands::Compare {
+            reference,
+            candidate,
             output,
-            format,
-        } => rumpus_cli::simulate::run(params, target, output, format),
+            colormap,
+        } => rumpus_cli::compare::run(reference, candidate, output, colormap),
+        Commands::Process(args) => rumpus_cli::intensity::run(args),
         _ => bail!("this command has not been implemented!"),
     }
 }
@@ -0,0 +1,427 @@
+use crate::cli::ParamsOverrides;
+use crate::cli::SkyModelKind;
+use anyhow::Context;
+use anyhow::Result;
+use chrono::LocalResult;
+use chrono::prelude::*;
+use chrono_tz::Tz;
+use rumpus::prelude::*;
+use sguaba::engineering::Orientation;
+use sguaba::systems::Wgs84;
+use std::{ffi::OsStr, io::Read, path::PathBuf};
+use uom::si::f64::Angle;
+use uom::si::f64::Length;
+use uom::si::{
+    angle::degree,
+    length::{meter, micron, millimeter},
+};
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub(crate) struct Params {
+    #[serde(flatten)]
+    pub(crate) pixel_size: PixelSize,
+    pub(crate) focal_length_mm: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) distortion: Option<RadialDistortion>,
+    pub(crate) image_rows: u16,
+    pub(crate) image_cols: u16,
+    pub(crate) yaw_deg: f64,
+    pub(crate) pitch_deg: f64,
+    pub(crate) roll_deg: f64,
+    pub(crate) lat_deg: f64,
+    pub(crate) lon_deg: f64,
+    #[serde(default)]
+    pub(crate) alt_m: f64,
+    #[serde(deserialize_with = "deserialize_time")]
+    pub(crate) time: DateTime<Utc>,
+    #[serde(default)]
+    pub(crate) sky_model: SkyModelKind,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            pixel_size: PixelSize {
+                pixel_width_um: 3.45 * 2.,
+                pixel_height_um: 3.45 * 2.,
+            },
+            focal_length_mm: 8.,
+            distortion: None,
+            image_rows: 1024,
+            image_cols: 1224,
+            yaw_deg: 0.,
+            pitch_deg: 0.,
+            roll_deg: 0.,
+            lat_deg: 44.2187,
+            lon_deg: -76.4747,
+            alt_m: 0.,
+            time: "2025-06-13T16:26:47+00:00".parse().unwrap(),
+            sky_model: SkyModelKind::Rayleigh,
+        }
+    }
+}
+
+/// Radial lens distortion coefficients of the Brown-Conrady model.
+///
+/// A point at undistorted radius `r`, normalized by the focal length, is
+/// imaged at `r * (1 + k1 * r^2 + k2 * r^4)`.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub(crate) struct RadialDistortion {
+    pub(crate) k1: f64,
+    #[serde(default)]
+    pub(crate) k2: f64,
+}
+
+/// Pixel dimensions of the sensor.
+///
+/// The deprecated `pixel_size_um` is still accepted for square pixels.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "PixelSizeFields")]
+pub(crate) struct PixelSize {
+    pub(crate) pixel_width_um: f64,
+    pub(crate) pixel_height_um: f64,
+}
+
+#[derive(serde::Deserialize)]
+struct PixelSizeFields {
+    pixel_size_um: Option<f64>,
+    pixel_width_um: Option<f64>,
+    pixel_height_um: Option<f64>,
+}
+
+impl TryFrom<PixelSizeFields> for PixelSize {
+    type Error = String;
+
+    fn try_from(fields: PixelSizeFields) -> Result<Self, Self::Error> {
+        match (
+            fields.pixel_width_um.or(fields.pixel_size_um),
+            fields.pixel_height_um.or(fields.pixel_size_um),
+        ) {
+            (Some(pixel_width_um), Some(pixel_height_um)) => Ok(Self {
+                pixel_width_um,
+                pixel_height_um,
+            }),
+            _ => Err("missing pixel_width_um and pixel_height_um (or pixel_size_um)".to_string()),
+        }
+    }
+}
+
+impl Params {
+    /// Read the params file, or the user config, or use the defaults, then apply the overrides.
+    pub(crate) fn load(path: &Option<PathBuf>, overrides: &ParamsOverrides) -> Result<Self> {
+        let mut params = match (path, user_config()) {
+            (Some(path), _) => parse_params(path)?,
+            (None, Some(config)) => parse_params(&config)
+                .with_context(|| format!("load user config {}", config.display()))?,
+            (None, None) => Params::default(),
+        };
+        // Flags given on the command line patch the params file.
+        params.apply_overrides(overrides)?;
+
+        Ok(params)
+    }
+
+    pub(crate) fn focal_length(&self) -> Length {
+        Length::new::<millimeter>(self.focal_length_mm)
+    }
+
+    pub(crate) fn lens(&self) -> Result<Lens> {
+        Lens::from_focal_length(self.focal_length()).context("positive focal length")
+    }
+
+    pub(crate) fn pixel_width(&self) -> Length {
+        Length::new::<micron>(self.pixel_size.pixel_width_um)
+    }
+
+    pub(crate) fn pixel_height(&self) -> Length {
+        Length::new::<micron>(self.pixel_size.pixel_height_um)
+    }
+
+    pub(crate) fn image_sensor(&self) -> ImageSensor {
+        ImageSensor::new(
+            self.pixel_width(),
+            self.pixel_height(),
+            self.image_rows(),
+            self.image_cols(),
+        )
+    }
+
+    pub(crate) fn image_rows(&self) -> u16 {
+        self.image_rows
+    }
+
+    pub(crate) fn image_cols(&self) -> u16 {
+        self.image_cols
+    }
+
+    pub(crate) fn wgs84(&self) -> Result<Wgs84> {
+        Ok(Wgs84::builder()
+            .longitude(Angle::new::<degree>(self.lon_deg))
+            .latitude(Angle::new::<degree>(self.lat_deg))
+            .context("latitude between -90 and 90 degrees")?
+            // Altitude is not used in the sky model yet.
+            .altitude(Length::new::<meter>(self.alt_m))
+            .build())
+    }
+
+    pub(crate) fn time(&self) -> DateTime<Utc> {
+        self.time
+    }
+
+    /// Solar azimuth and zenith angle in degrees for the observer at the configured time.
+    pub(crate) fn solar_position(&self) -> Result<spa::SolarPos> {
+        spa::solar_position::<spa::StdFloatOps>(self.time(), self.lat_deg, self.lon_deg)
+            .context("solar position for the configured latitude, longitude, and time")
+    }
+
+    /// Merge each N x N block of pixels into one larger pixel at the block center.
+    pub(crate) fn binned(&self, bin: u16) -> Self {
+        let scale = f64::from(bin);
+        Self {
+            pixel_size: PixelSize {
+                pixel_width_um: self.pixel_size.pixel_width_um * scale,
+                pixel_height_um: self.pixel_size.pixel_height_um * scale,
+            },
+            image_rows: self.image_rows / bin,
+            image_cols: self.image_cols / bin,
+            ..self.clone()
+        }
+    }
+
+    /// Sky model of the selected kind for the observer position and time.
+    pub(crate) fn sky_model(&self) -> Result<SkyModel> {
+        Ok(match self.sky_model {
+            SkyModelKind::Rayleigh => SkyModel::from_wgs84_and_time(self.wgs84()?, self.time()),
+        })
+    }
+
+    /// Construct every component of the simulation to surface configuration errors.
+    pub(crate) fn validate(&self) -> Result<()> {
+        self.sky_model()?;
+        self.lens()?;
+        self.solar_position()?;
+
+        if self.pixel_size.pixel_width_um <= 0. || self.pixel_size.pixel_height_um <= 0. {
+            anyhow::bail!("pixel dimensions must be positive");
+        }
+        if self.image_rows() == 0 || self.image_cols() == 0 {
+            anyhow::bail!("image must have at least one row and one column");
+        }
+        if ![self.yaw_deg, self.pitch_deg, self.roll_deg]
+            .iter()
+            .all(|angle| angle.is_finite())
+        {
+            anyhow::bail!("yaw, pitch, and roll must be finite");
+        }
+
+        let image_sensor = self.image_sensor();
+        let (last_row, last_col) = (self.image_rows() - 1, self.image_cols() - 1);
+        for (row, col) in [(0, 0), (0, last_col), (last_row, 0), (last_row, last_col)] {
+            image_sensor
+                .at_pixel(row, col)
+                .with_context(|| format!("pixel at row {row} col {col} is on the sensor"))?;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn orientation(&self) -> Orientation<CameraEnu> {
+        Orientation::<CameraEnu>::tait_bryan_builder()
+            .yaw(Angle::new::<degree>(self.yaw_deg))
+            .pitch(Angle::new::<degree>(self.pitch_deg))
+            .roll(Angle::new::<degree>(self.roll_deg))
+            .build()
+    }
+
+    fn apply_overrides(&mut self, overrides: &ParamsOverrides) -> Result<()> {
+        if let Some(yaw_deg) = overrides.yaw_deg {
+            self.yaw_deg = yaw_deg;
+        }
+        if let Some(pitch_deg) = overrides.pitch_deg {
+            self.pitch_deg = pitch_deg;
+        }
+        if let Some(roll_deg) = overrides.roll_deg {
+            self.roll_deg = roll_deg;
+        }
+        if let Some(lat_deg) = overrides.lat_deg {
+            self.lat_deg = lat_deg;
+        }
+        if let Some(lon_deg) = overrides.lon_deg {
+            self.lon_deg = lon_deg;
+        }
+        if let Some(alt_m) = overrides.alt_m {
+            self.alt_m = alt_m;
+        }
+        if let Some(time) = &overrides.time {
+            self.time = parse_time(time, overrides.timezone.as_deref())?;
+        }
+        if let Some(focal_length_mm) = overrides.focal_length_mm {
+            self.focal_length_mm = focal_length_mm;
+        }
+        if let Some(pixel_size_um) = overrides.pixel_size_um {
+            self.pixel_size.pixel_width_um = pixel_size_um;
+            self.pixel_size.pixel_height_um = pixel_size_um;
+        }
+        if let Some(pixel_width_um) = overrides.pixel_width_um {
+            self.pixel_size.pixel_width_um = pixel_width_um;
+        }
+        if let Some(pixel_height_um) = overrides.pixel_height_um {
+            self.pixel_size.pixel_height_um = pixel_height_um;
+        }
+        if let Some(sky_model) = overrides.sky_model {
+            self.sky_model = sky_model;
+        }
+
+        Ok(())
+    }
+}
+
+/// Parse a time as RFC3339, or as a local time in a timezone.
+///
+/// The timezone is an IANA name or a UTC offset, and can also follow the local
+/// time after a space, e.g. `2025-06-13 12:26:47 America/Toronto`.
+pub(crate) fn parse_time(time: &str, timezone: Option<&str>) -> Result<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(time) {
+        if let Some(timezone) = timezone {
+            anyhow::bail!("time {time} already has an offset, so timezone {timezone} conflicts");
+        }
+        return Ok(time.to_utc());
+    }
+
+    let parse_local = |local: &str| {
+        ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"]
+            .iter()
+            .find_map(|format| NaiveDateTime::parse_from_str(local.trim(), format).ok())
+    };
+    let (local, timezone) = match (timezone, time.trim().rsplit_once(' ')) {
+        (Some(timezone), _) => (time.trim(), timezone),
+        // A bare local time would otherwise split its clock off as the timezone.
+        (None, _) if parse_local(time).is_some() => {
+            anyhow::bail!("time {time} needs an offset or a timezone")
+        }
+        (None, Some((local, timezone))) => (local, timezone),
+        (None, None) => anyhow::bail!("time {time} needs an offset or a timezone"),
+    };
+
+    let local = parse_local(local)
+        .with_context(|| format!("time {local} is not formatted like 2025-06-13 12:26:47"))?;
+
+    let resolved = if let Ok(tz) = timezone.parse::<Tz>() {
+        tz.from_local_datetime(&local).map(|time| time.to_utc())
+    } else if let Ok(offset) = timezone.parse::<FixedOffset>() {
+        offset.from_local_datetime(&local).map(|time| time.to_utc())
+    } else {
+        anyhow::bail!("unknown timezone {timezone}");
+    };
+
+    // Clocks changing for daylight saving skip or repeat an hour of local times.
+    match resolved {
+        LocalResult::Single(time) => Ok(time),
+        LocalResult::Ambiguous(earliest, latest) => anyhow::bail!(
+            "time {local} occurs twice in {timezone}, at {earliest} and {latest}, use an offset"
+        ),
+        LocalResult::None => anyhow::bail!("time {local} does not exist in {timezone}"),
+    }
+}
+
+fn deserialize_time<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let time = <String as serde::Deserialize>::deserialize(deserializer)?;
+    parse_time(&time, None).map_err(|err| serde::de::Error::custom(format!("{err:#}")))
+}
+
+// Params for runs without --params, e.g. `~/.config/rumpus_cli/config.toml` on Linux.
+fn user_config() -> Option<PathBuf> {
+    let path = dirs::config_dir()?.join("rumpus_cli").join("config.toml");
+    path.is_file().then_some(path)
+}
+
+pub(crate) fn parse_params(path: &PathBuf) -> Result<Params> {
+    let mut buffer = String::new();
+    std::fs::File::open(path)?.read_to_string(&mut buffer)?;
+
+    let params = match path
+        .as_path()
+        .extension()
+        .map(|os_str: &OsStr| os_str.to_str())
+    {
+        Some(Some("toml")) => toml::from_str(&buffer)?,
+        Some(Some("json")) => serde_json::from_str(&buffer)?,
+        Some(Some("yaml" | "yml")) => serde_yaml::from_str(&buffer)?,
+        Some(extension) => anyhow::bail!(
+            "unsupported params file extension: {}",
+            extension.unwrap_or("<non-utf8>")
+        ),
+        // Without an extension, try each format in turn.
+        None => match toml::from_str(&buffer) {
+            Ok(params) => params,
+            Err(_) => serde_json::from_str(&buffer)
+                .context("params file is neither valid TOML nor JSON")?,
+        },
+    };
+
+    Ok(params)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(time).unwrap().to_utc()
+    }
+
+    #[test]
+    fn parse_time_accepts_rfc3339() {
+        let time = parse_time("2025-06-13T16:26:47+00:00", None).unwrap();
+        assert_eq!(time, utc("2025-06-13T16:26:47Z"));
+    }
+
+    #[test]
+    fn parse_time_resolves_an_iana_timezone() {
+        let time = parse_time("2025-06-13 12:26:47", Some("America/Toronto")).unwrap();
+        assert_eq!(time, utc("2025-06-13T16:26:47Z"));
+    }
+
+    #[test]
+    fn parse_time_resolves_a_fixed_offset() {
+        let time = parse_time("2025-06-13T12:26:47", Some("-04:00")).unwrap();
+        assert_eq!(time, utc("2025-06-13T16:26:47Z"));
+    }
+
+    #[test]
+    fn parse_time_reads_a_timezone_suffix() {
+        let time = parse_time("2025-06-13 12:26:47 America/Toronto", None).unwrap();
+        assert_eq!(time, utc("2025-06-13T16:26:47Z"));
+    }
+
+    #[test]
+    fn parse_time_rejects_a_timezone_alongside_an_offset() {
+        let err = parse_time("2025-06-13T16:26:47+00:00", Some("America/Toronto")).unwrap_err();
+        assert!(err.to_string().contains("conflicts"), "{err}");
+    }
+
+    #[test]
+    fn parse_time_reports_a_missing_timezone() {
+        for time in ["2025-06-13 12:26:47", "2025-06-13T12:26:47"] {
+            let err = parse_time(time, None).unwrap_err();
+            assert!(err.to_string().contains("needs an offset"), "{err}");
+        }
+    }
+
+    #[test]
+    fn parse_time_rejects_a_skipped_local_time() {
+        // Clocks in Toronto jump from 02:00 to 03:00 on 2025-03-09.
+        let err = parse_time("2025-03-09 02:30:00", Some("America/Toronto")).unwrap_err();
+        assert!(err.to_string().contains("does not exist"), "{err}");
+    }
+
+    #[test]
+    fn parse_time_rejects_a_repeated_local_time() {
+        // Clocks in Toronto fall back from 02:00 to 01:00 on 2025-11-02.
+        let err = parse_time("2025-11-02 01:30:00", Some("America/Toronto")).unwrap_err();
+        assert!(err.to_string().contains("occurs twice"), "{err}");
+    }
+}
@@ -1,110 +1,486 @@
+use crate::cli::AngleUnit;
+use crate::cli::BitDepth;
+use crate::cli::Colormap;
+use crate::cli::OutputOptions;
+use crate::cli::Projection;
+use crate::cli::SimulateArgs;
 use crate::cli::SimulationFormat;
+use crate::cli::SimulationFrame;
 use crate::cli::SimulationTarget;
+use crate::cli::Sweep;
+use crate::cli::Transform;
+use crate::legend;
+use crate::params::Params;
+use crate::params::RadialDistortion;
 use anyhow::Context;
 use anyhow::Result;
+use chrono::TimeDelta;
 use chrono::prelude::*;
+use image::ImageEncoder;
+use image::codecs::png::PngEncoder;
+use indicatif::{ProgressBar, ProgressStyle};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rand_distr::{Distribution, Normal};
 use rayon::prelude::*;
 use rumpus::prelude::*;
 use sguaba::Coordinate;
-use sguaba::engineering::Orientation;
-use sguaba::systems::Wgs84;
 use std::{
     ffi::OsStr,
     fs::File,
-    io::{BufWriter, Read, Write},
+    io::{self, BufWriter, IsTerminal, Write},
+    ops::Range,
     path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
 };
-use uom::si::f64::Angle;
-use uom::si::f64::Length;
+use tiff::encoder::{TiffEncoder, colortype};
+use tiff::tags::Tag;
+use uom::si::f64::{Angle, Length};
 use uom::si::{
-    angle::degree,
-    length::{meter, micron, millimeter},
+    angle::{degree, radian},
+    length::millimeter,
 };
 
-#[derive(serde::Serialize, serde::Deserialize)]
-struct Params {
-    pixel_size_um: f64,
-    focal_length_mm: f64,
-    image_rows: u16,
-    image_cols: u16,
-    yaw_deg: f64,
-    pitch_deg: f64,
-    roll_deg: f64,
-    lat_deg: f64,
-    lon_deg: f64,
-    time: DateTime<Utc>,
-}
-
-impl Default for Params {
-    fn default() -> Self {
-        Self {
-            pixel_size_um: 3.45 * 2.,
-            focal_length_mm: 8.,
-            image_rows: 1024,
-            image_cols: 1224,
-            yaw_deg: 0.,
-            pitch_deg: 0.,
-            roll_deg: 0.,
-            lat_deg: 44.2187,
-            lon_deg: -76.4747,
-            time: "2025-06-13T16:26:47+00:00".parse().unwrap(),
+pub fn run(args: &SimulateArgs) -> Result<()> {
+    let start = Instant::now();
+    let mut params = Params::load(&args.params, &args.overrides)?;
+
+    if args.info {
+        if args.output.as_ref().is_some_and(is_stdout) {
+            anyhow::bail!("--info prints to stdout, which would corrupt the streamed output");
+        }
+        print_info(&params, &args.projection)?;
+    }
+    let Some(output) = &args.output else {
+        return Ok(());
+    };
+    if args.bin > 1 {
+        params = params.binned(args.bin);
+        if params.image_rows() == 0 || params.image_cols() == 0 {
+            anyhow::bail!("bin {} is larger than the sensor", args.bin);
+        }
+    }
+    tracing::debug!("resolved params: {}", serde_json::to_string(&params)?);
+
+    // The optics are shared by every frame, so only build them once.
+    let lens = params.lens()?;
+    let image_sensor = params.image_sensor();
+    let mask = args
+        .mask
+        .as_ref()
+        .map(|path| load_mask(path, &params))
+        .transpose()?;
+
+    // An orientation sweep needs one camera per orientation.
+    let cameras: Vec<(Option<[f64; 3]>, Camera)> = match orientation_sweep(args, &params) {
+        Some(orientations) => orientations
+            .into_iter()
+            .map(|[yaw_deg, pitch_deg, roll_deg]| {
+                let params = Params {
+                    yaw_deg,
+                    pitch_deg,
+                    roll_deg,
+                    ..params.clone()
+                };
+                let camera = Camera::new(lens.clone(), params.orientation());
+                (Some([yaw_deg, pitch_deg, roll_deg]), camera)
+            })
+            .collect(),
+        None => vec![(None, Camera::new(lens.clone(), params.orientation()))],
+    };
+
+    // Draw all noise from one generator so seeded runs are reproducible.
+    let mut noise = Noise::from_args(args)?;
+    check_output_options(&args.output_options)?;
+
+    // A time series renders one numbered frame per timestamp.
+    let frames: Vec<(Option<usize>, DateTime<Utc>)> = match time_series(args)? {
+        Some(times) => times
+            .into_iter()
+            .enumerate()
+            .map(|(index, time)| (Some(index), time))
+            .collect(),
+        None => vec![(None, params.time())],
+    };
+    check_stdout(args, output, frames.len() * cameras.len())?;
+
+    // Fail before tracing anything rather than part way through a series.
+    // The writers still note each file they replace.
+    if args.output_options.no_clobber {
+        for (index, _) in &frames {
+            for (orientation, _) in &cameras {
+                for path in output_paths(args, output, &frame_suffix(*index, *orientation))? {
+                    check_clobber(&path, true)?;
+                }
+            }
         }
     }
+
+    for (index, time) in frames {
+        params.time = time;
+        let sky_model = params.sky_model()?;
+
+        for (orientation, camera) in &cameras {
+            let suffix = frame_suffix(index, *orientation);
+            if let Some([yaw_deg, pitch_deg, roll_deg]) = *orientation {
+                params.yaw_deg = yaw_deg;
+                params.pitch_deg = pitch_deg;
+                params.roll_deg = roll_deg;
+            }
+
+            tracing::debug!(
+                "tracing at {} with yaw {} pitch {} roll {}",
+                params.time(),
+                params.yaw_deg,
+                params.pitch_deg,
+                params.roll_deg
+            );
+            let tracer = Tracer {
+                params: &params,
+                image_sensor: &image_sensor,
+                camera,
+                sky_model: &sky_model,
+                frame: &args.frame,
+                projection: &args.projection,
+                mask: mask.as_deref(),
+            };
+            let options = &args.output_options;
+            let output = suffixed_path(output, &suffix);
+            if let Some(tile_rows) = args.tile_rows {
+                write_tiled(&tracer, args, &mut noise, &output, tile_rows)?;
+            } else {
+                let progress = progress_bar(&params, args.quiet)?;
+                let mut image = tracer.trace(0..params.image_rows(), &progress)?;
+                progress.finish_and_clear();
+                report_populated(
+                    image.populated(),
+                    image.skipped,
+                    image.pixels.len(),
+                    args.quiet,
+                );
+
+                if let Some(noise) = &mut noise {
+                    noise.apply(&mut image);
+                }
+                if let Some(transform) = &args.transform {
+                    image = image.transformed(transform);
+                }
+
+                write_output(
+                    &image,
+                    &params,
+                    &args.target,
+                    &output,
+                    &args.format,
+                    options,
+                )?;
+                for (target, path) in [
+                    (SimulationTarget::Dop, &args.dop_output),
+                    (SimulationTarget::ScatteringAngle, &args.scattering_angle),
+                ] {
+                    if let Some(path) = path {
+                        write_output(
+                            &image,
+                            &params,
+                            &target,
+                            &suffixed_path(path, &suffix),
+                            &None,
+                            options,
+                        )?;
+                    }
+                }
+                if args.stokes {
+                    for (target, tag) in [(SimulationTarget::Q, "_q"), (SimulationTarget::U, "_u")]
+                    {
+                        write_output(
+                            &image,
+                            &params,
+                            &target,
+                            &suffixed_path(&output, tag),
+                            &args.format,
+                            options,
+                        )?;
+                    }
+                }
+            }
+            if args.metadata {
+                write_metadata(
+                    &params,
+                    &args.projection,
+                    &output.with_extension("json"),
+                    options,
+                )?;
+            }
+        }
+    }
+    tracing::info!("finished in {:.2?}", start.elapsed());
+
+    Ok(())
+}
+
+/// Record of the resolved params that produced an output.
+#[derive(serde::Serialize)]
+struct Metadata<'a> {
+    version: &'static str,
+    created: DateTime<Utc>,
+    params: &'a Params,
+    projection: &'a Projection,
 }
 
-impl Params {
-    fn focal_length(&self) -> Length {
-        Length::new::<millimeter>(self.focal_length_mm)
+fn write_metadata(
+    params: &Params,
+    projection: &Projection,
+    path: &PathBuf,
+    options: &OutputOptions,
+) -> Result<()> {
+    let metadata = Metadata {
+        version: env!("CARGO_PKG_VERSION"),
+        created: Utc::now(),
+        params,
+        projection,
+    };
+
+    check_clobber(path, options.no_clobber)?;
+    let mut output_file = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut output_file, &metadata)?;
+    writeln!(output_file)?;
+
+    Ok(())
+}
+
+// Every combination of the swept yaw, pitch, and roll angles.
+// Axes without a sweep keep the angle from the params.
+fn orientation_sweep(args: &SimulateArgs, params: &Params) -> Option<Vec<[f64; 3]>> {
+    if args.yaw_sweep.is_none() && args.pitch_sweep.is_none() && args.roll_sweep.is_none() {
+        return None;
     }
 
-    fn pixel_size(&self) -> Length {
-        Length::new::<micron>(self.pixel_size_um)
+    let values = |sweep: &Option<Sweep>, fixed: f64| match sweep {
+        Some(sweep) => sweep.values(),
+        None => vec![fixed],
+    };
+
+    let mut orientations = Vec::new();
+    for yaw_deg in values(&args.yaw_sweep, params.yaw_deg) {
+        for pitch_deg in values(&args.pitch_sweep, params.pitch_deg) {
+            for roll_deg in values(&args.roll_sweep, params.roll_deg) {
+                orientations.push([yaw_deg, pitch_deg, roll_deg]);
+            }
+        }
     }
 
-    fn image_rows(&self) -> u16 {
-        self.image_rows
+    Some(orientations)
+}
+
+// Report the optics derived from the params and whether the sun is up.
+fn print_info(params: &Params, projection: &Projection) -> Result<()> {
+    let focal_length_mm = params.focal_length_mm;
+    let width_mm = f64::from(params.image_cols()) * params.pixel_size.pixel_width_um / 1000.;
+    let height_mm = f64::from(params.image_rows()) * params.pixel_size.pixel_height_um / 1000.;
+    let diagonal_mm = width_mm.hypot(height_mm);
+    // An equidistant fisheye images angle r / f off the axis, a pinhole atan(r / f).
+    let fov_deg = |extent_mm: f64| match projection {
+        Projection::FisheyeEquidistant => (extent_mm / focal_length_mm).to_degrees(),
+        _ => 2. * (extent_mm / 2.).atan2(focal_length_mm).to_degrees(),
+    };
+
+    println!("sensor size:     {width_mm:.3} x {height_mm:.3} mm");
+    println!("sensor diagonal: {diagonal_mm:.3} mm");
+    println!("horizontal fov:  {:.3} deg", fov_deg(width_mm));
+    println!("vertical fov:    {:.3} deg", fov_deg(height_mm));
+    println!("diagonal fov:    {:.3} deg", fov_deg(diagonal_mm));
+
+    let elevation_deg = 90. - params.solar_position()?.zenith_angle;
+    let horizon = if elevation_deg < 0. { "below" } else { "above" };
+    println!("sun elevation:   {elevation_deg:.3} deg ({horizon} the horizon)");
+
+    Ok(())
+}
+
+// Read a grayscale mask the size of the image, where zero pixels are skipped.
+fn load_mask(path: &PathBuf, params: &Params) -> Result<Vec<bool>> {
+    let mask = image::open(path)
+        .with_context(|| format!("open mask {}", path.display()))?
+        .into_luma8();
+
+    let expected = (
+        u32::from(params.image_cols()),
+        u32::from(params.image_rows()),
+    );
+    if mask.dimensions() != expected {
+        anyhow::bail!(
+            "mask is {}x{} but the image is {}x{}",
+            mask.width(),
+            mask.height(),
+            expected.0,
+            expected.1
+        );
     }
 
-    fn image_cols(&self) -> u16 {
-        self.image_cols
+    Ok(mask.pixels().map(|pixel| pixel.0[0] != 0).collect())
+}
+
+// Expand --time-start, --time-end, and --time-step into each frame's time.
+fn time_series(args: &SimulateArgs) -> Result<Option<Vec<DateTime<Utc>>>> {
+    let (Some(start), Some(end)) = (args.time_start, args.time_end) else {
+        return Ok(None);
+    };
+
+    if end < start {
+        anyhow::bail!("time-end {end} is before time-start {start}");
+    }
+    if start == end {
+        return Ok(Some(vec![start]));
     }
 
-    fn wgs84(&self) -> Result<Wgs84> {
-        Ok(Wgs84::builder()
-            .longitude(Angle::new::<degree>(self.lon_deg))
-            .latitude(Angle::new::<degree>(self.lat_deg))
-            .context("latitude between -90 and 90 degrees")?
-            // Altitude is not used in the sky model.
-            .altitude(Length::new::<meter>(0.0))
-            .build())
+    let step = args
+        .time_step
+        .context("time-step is required when time-start and time-end differ")?;
+    if step <= TimeDelta::zero() {
+        anyhow::bail!("time-step must be positive");
     }
 
-    fn time(&self) -> DateTime<Utc> {
-        self.time
+    let mut times = Vec::new();
+    let mut time = start;
+    while time <= end {
+        times.push(time);
+        time += step;
     }
 
-    fn orientation(&self) -> Orientation<CameraEnu> {
-        Orientation::<CameraEnu>::tait_bryan_builder()
-            .yaw(Angle::new::<degree>(self.yaw_deg))
-            .pitch(Angle::new::<degree>(self.pitch_deg))
-            .roll(Angle::new::<degree>(self.roll_deg))
-            .build()
+    Ok(Some(times))
+}
+
+// Encode the frame index and orientation into the file names.
+fn frame_suffix(index: Option<usize>, orientation: Option<[f64; 3]>) -> String {
+    let mut suffix = String::new();
+    if let Some(index) = index {
+        suffix.push_str(&format!("_{index:04}"));
     }
+    if let Some([yaw_deg, pitch_deg, roll_deg]) = orientation {
+        suffix.push_str(&format!("_y{yaw_deg}_p{pitch_deg}_r{roll_deg}"));
+    }
+
+    suffix
 }
 
-pub fn run(
-    params: &Option<PathBuf>,
-    target: &SimulationTarget,
-    output: &PathBuf,
-    format: &Option<SimulationFormat>,
-) -> Result<()> {
-    let params = match params {
-        Some(path) => parse_params(&path)?,
-        None => Params::default(),
+// Every file written for one frame, including the ENVI headers and sidecar.
+fn output_paths(args: &SimulateArgs, output: &PathBuf, suffix: &str) -> Result<Vec<PathBuf>> {
+    let output = suffixed_path(output, suffix);
+    let mut outputs = vec![(output.clone(), args.format)];
+    for path in [&args.dop_output, &args.scattering_angle]
+        .into_iter()
+        .flatten()
+    {
+        outputs.push((suffixed_path(path, suffix), None));
+    }
+    if args.stokes {
+        for tag in ["_q", "_u"] {
+            outputs.push((suffixed_path(&output, tag), args.format));
+        }
+    }
+
+    let mut paths = Vec::new();
+    for (path, format) in outputs {
+        if is_stdout(&path) {
+            continue;
+        }
+        if args.output_options.envi && output_format(&path, &format)? == SimulationFormat::Dat {
+            paths.push(path.with_extension("hdr"));
+        }
+        paths.push(path);
+    }
+    if args.metadata {
+        paths.push(output.with_extension("json"));
+    }
+
+    Ok(paths)
+}
+
+// Stdout has no name to suffix or sidecar, so it can only carry a single output.
+fn check_stdout(args: &SimulateArgs, output: &PathBuf, frames: usize) -> Result<()> {
+    let streamed = [
+        Some(output),
+        args.dop_output.as_ref(),
+        args.scattering_angle.as_ref(),
+    ]
+    .into_iter()
+    .flatten()
+    .filter(|path| is_stdout(path))
+    .count();
+    if streamed == 0 {
+        return Ok(());
+    }
+
+    if streamed > 1 {
+        anyhow::bail!("only one output can be written to stdout");
+    }
+    if frames > 1 {
+        anyhow::bail!("time series and sweeps write one output per frame, which need a file path");
+    }
+    if is_stdout(output) && args.stokes {
+        anyhow::bail!("--stokes writes _q and _u outputs, which need a file path");
+    }
+    if is_stdout(output) && args.metadata {
+        anyhow::bail!("--metadata names the sidecar after the output, which needs a file path");
+    }
+
+    Ok(())
+}
+
+// Insert a suffix before the extension, e.g. out.png -> out_0001.png.
+fn suffixed_path(path: &PathBuf, suffix: &str) -> PathBuf {
+    if suffix.is_empty() || is_stdout(path) {
+        return path.clone();
+    }
+
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .unwrap_or_default();
+    let file_name = match path.extension() {
+        Some(extension) => format!("{stem}{suffix}.{}", extension.to_string_lossy()),
+        None => format!("{stem}{suffix}"),
     };
 
-    let ray_image = simulate(&params)?;
+    path.with_file_name(file_name)
+}
+
+// Refuse to replace an existing output with --no-clobber, otherwise note it.
+pub(crate) fn check_clobber(path: &PathBuf, no_clobber: bool) -> Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+    if no_clobber {
+        anyhow::bail!("{} already exists", path.display());
+    }
+
+    tracing::info!("replacing {}", path.display());
+    Ok(())
+}
+
+fn is_stdout(path: &PathBuf) -> bool {
+    path.as_os_str() == "-"
+}
 
+// Open an output for writing, treating a path of `-` as stdout.
+// Binary formats are only streamed when stdout is redirected away from a terminal.
+fn create_output(path: &PathBuf, binary: bool, options: &OutputOptions) -> Result<Box<dyn Write>> {
+    if !is_stdout(path) {
+        check_clobber(path, options.no_clobber)?;
+        return Ok(Box::new(BufWriter::new(File::create(path)?)));
+    }
+
+    let stdout = io::stdout();
+    if binary && stdout.is_terminal() {
+        anyhow::bail!("refusing to write binary output to a terminal, redirect stdout instead");
+    }
+
+    Ok(Box::new(BufWriter::new(stdout.lock())))
+}
+
+// Use the requested format, otherwise infer it from the file extension.
+fn output_format(output: &PathBuf, format: &Option<SimulationFormat>) -> Result<SimulationFormat> {
     match format.or_else(|| {
         match output
             .as_path()
@@ -113,104 +489,483 @@ pub fn run(
         {
             Some(Some("png")) => Some(SimulationFormat::Png),
             Some(Some("dat")) => Some(SimulationFormat::Dat),
+            Some(Some("csv")) => Some(SimulationFormat::Csv),
+            Some(Some("fits" | "fit")) => Some(SimulationFormat::Fits),
+            Some(Some("npy")) => Some(SimulationFormat::Npy),
+            Some(Some("tif" | "tiff")) => Some(SimulationFormat::GeoTiff),
             _ => None,
         }
     }) {
-        Some(format) => match format {
-            SimulationFormat::Png => write_image(
-                ray_image,
-                params.image_rows(),
-                params.image_cols(),
-                target,
-                output,
-            ),
-            SimulationFormat::Dat => write_dat(
-                ray_image,
-                params.image_rows(),
-                params.image_cols(),
-                target,
-                output,
-            ),
-        },
+        Some(format) => Ok(format),
+        None if is_stdout(output) => {
+            anyhow::bail!("the output format cannot be inferred when writing to stdout")
+        }
         None => anyhow::bail!("unsupported output format"),
     }
 }
 
-fn simulate(params: &Params) -> Result<RayImage<GlobalFrame>> {
-    let lens = Lens::from_focal_length(params.focal_length()).expect("positive focal length");
-    let image_sensor = ImageSensor::new(
-        params.pixel_size(),
-        params.pixel_size(),
-        params.image_rows(),
-        params.image_cols(),
+fn write_output(
+    image: &SimulatedImage,
+    params: &Params,
+    target: &SimulationTarget,
+    output: &PathBuf,
+    format: &Option<SimulationFormat>,
+    options: &OutputOptions,
+) -> Result<()> {
+    match output_format(output, format)? {
+        SimulationFormat::Png => write_image(image, target, output, options),
+        SimulationFormat::Dat => write_dat(image, target, output, options),
+        SimulationFormat::Csv => write_csv(image, target, output, options),
+        SimulationFormat::Fits => write_fits(image, params, target, output, options),
+        SimulationFormat::Npy => write_npy(image, target, output, options),
+        SimulationFormat::GeoTiff => write_geotiff(image, params, target, output, options),
+    }
+}
+
+// Trace and write a few rows at a time so only one tile is ever held in memory.
+// The output is identical to tracing the whole sensor at once.
+fn write_tiled(
+    tracer: &Tracer,
+    args: &SimulateArgs,
+    noise: &mut Option<Noise>,
+    output: &PathBuf,
+    tile_rows: u16,
+) -> Result<()> {
+    let params = tracer.params;
+    let format = output_format(output, &args.format)?;
+    if !matches!(format, SimulationFormat::Dat | SimulationFormat::Csv) {
+        anyhow::bail!("--tile-rows only supports dat and csv outputs");
+    }
+
+    let options = &args.output_options;
+    if format == SimulationFormat::Dat && options.envi {
+        write_envi_header(
+            output,
+            params.image_rows(),
+            params.image_cols(),
+            &args.target,
+            options,
+        )?;
+    }
+    let mut output_file = create_output(output, false, options)?;
+    if format == SimulationFormat::Dat && !options.envi {
+        write_dat_header(&mut output_file, &args.target, options)?;
+    }
+    if format == SimulationFormat::Csv && options.header {
+        write_csv_header(&mut output_file, params.image_cols(), &args.target, options)?;
+    }
+
+    let progress = progress_bar(params, args.quiet)?;
+    let (mut populated, mut skipped, mut total) = (0, 0, 0);
+    for rows in tile_ranges(params.image_rows(), tile_rows) {
+        let start = rows.start;
+        let mut tile = tracer.trace(rows, &progress)?;
+        populated += tile.populated();
+        skipped += tile.skipped;
+        total += tile.pixels.len();
+
+        if let Some(noise) = noise {
+            noise.apply(&mut tile);
+        }
+        match format {
+            SimulationFormat::Csv => {
+                write_csv_rows(&mut output_file, &tile, start, &args.target, options)?
+            }
+            _ if options.envi => write_envi_rows(&mut output_file, &tile, &args.target, options)?,
+            _ => write_dat_rows(&mut output_file, &tile, &args.target, options)?,
+        }
+    }
+    progress.finish_and_clear();
+    report_populated(populated, skipped, total, args.quiet);
+
+    Ok(())
+}
+
+// Only draw progress for interactive sessions so logs stay clean.
+fn progress_bar(params: &Params, quiet: bool) -> Result<ProgressBar> {
+    if quiet || !std::io::stderr().is_terminal() {
+        return Ok(ProgressBar::hidden());
+    }
+
+    let pixels = u64::from(params.image_rows()) * u64::from(params.image_cols());
+    Ok(
+        ProgressBar::new(pixels).with_style(ProgressStyle::with_template(
+            "{wide_bar} {pos}/{len} pixels (eta {eta})",
+        )?),
+    )
+}
+
+// Bearings the sky model has no value for, e.g. below the horizon, are left empty.
+// Masked pixels and those outside the image circle are never traced at all.
+fn report_populated(populated: usize, skipped: usize, total: usize, quiet: bool) {
+    tracing::info!("traced {} pixels", total - skipped);
+    tracing::info!("skipped {skipped} masked pixels or pixels outside the image circle");
+    tracing::info!(
+        "dropped {} rays without a sky model value",
+        total - skipped - populated
     );
-    let coords: Vec<Coordinate<CameraFrd>> = (0..params.image_rows())
-        .flat_map(|row| (0..params.image_cols()).map(move |col| (row, col)))
-        .map(|(row, col)| image_sensor.at_pixel(row, col).unwrap())
-        .collect();
 
-    let sky_model = SkyModel::from_wgs84_and_time(params.wgs84()?, params.time());
-    let cam_orientation = params.orientation();
+    if !quiet {
+        // Stderr keeps the summary out of outputs streamed to stdout.
+        eprintln!(
+            "{populated}/{total} pixels populated ({:.1}%)",
+            100. * populated as f64 / total as f64
+        );
+    }
+}
 
-    let camera = Camera::new(lens.clone(), cam_orientation);
-    let rays: Vec<Ray<_>> = coords
-        .par_iter()
-        .filter_map(|coord| {
-            let bearing_cam_enu = camera
-                .trace_from_sensor(*coord)
-                .expect("coord on sensor plane");
-            let aop = sky_model.aop(bearing_cam_enu)?;
-            let dop = sky_model.dop(bearing_cam_enu)?;
+/// Everything needed to trace the sensor of one camera at one time.
+pub(crate) struct Tracer<'a> {
+    pub(crate) params: &'a Params,
+    pub(crate) image_sensor: &'a ImageSensor,
+    pub(crate) camera: &'a Camera,
+    pub(crate) sky_model: &'a SkyModel,
+    pub(crate) frame: &'a SimulationFrame,
+    pub(crate) projection: &'a Projection,
+    /// Pixels to trace in row-major order, or every pixel if not provided.
+    pub(crate) mask: Option<&'a [bool]>,
+}
+
+impl Tracer<'_> {
+    // Trace the given rows of the sensor into samples in row-major order.
+    pub(crate) fn trace(&self, rows: Range<u16>, progress: &ProgressBar) -> Result<SimulatedImage> {
+        let Self {
+            params,
+            image_sensor,
+            camera,
+            sky_model,
+            frame,
+            projection,
+            mask,
+        } = self;
+        let cols = usize::from(params.image_cols());
+        let sun_enu = sun_direction(params)?;
+        let skipped = AtomicUsize::new(0);
+
+        // Compute each sensor coordinate inside the trace rather than collecting
+        // them all up front. Indexed collection keeps the samples in pixel order.
+        let pixels = (usize::from(rows.start) * cols..usize::from(rows.end) * cols)
+            .into_par_iter()
+            .map(|i| {
+                progress.inc(1);
+                // Masked pixels are left missing without being traced.
+                if mask.is_some_and(|mask| !mask[i]) {
+                    skipped.fetch_add(1, Ordering::Relaxed);
+                    return Ok(None);
+                }
+                let (row, col) = pixel_index(i, cols);
+                let sensor_coord = image_sensor
+                    .at_pixel(row, col)
+                    .with_context(|| format!("pixel at row {row} col {col} is on the sensor"))?;
+                // The sample stays at its own pixel, the undistorted coordinate
+                // only decides which bearing the pixel sees.
+                let coord = match &params.distortion {
+                    Some(distortion) => undistort(sensor_coord, distortion, params.focal_length()),
+                    None => sensor_coord,
+                };
+                // Pixels outside the fisheye image circle see no sky.
+                let coord = match projection {
+                    Projection::FisheyeEquidistant => {
+                        match fisheye_to_rectilinear(coord, params.focal_length()) {
+                            Some(coord) => coord,
+                            None => {
+                                skipped.fetch_add(1, Ordering::Relaxed);
+                                return Ok(None);
+                            }
+                        }
+                    }
+                    _ => coord,
+                };
+
+                let bearing_cam_enu = camera
+                    .trace_from_sensor(coord)
+                    .with_context(|| format!("trace the ray at row {row} col {col}"))?;
+                let (Some(aop), Some(dop)) = (
+                    sky_model.aop(bearing_cam_enu),
+                    sky_model.dop(bearing_cam_enu),
+                ) else {
+                    return Ok(None);
+                };
+
+                let aop_deg = aop.angle().get::<degree>();
+                let scattering_deg = scattering_angle_deg(
+                    bearing_cam_enu.azimuth(),
+                    bearing_cam_enu.elevation(),
+                    sun_enu,
+                );
+                Ok(Some(Sample {
+                    aop_deg: match frame {
+                        SimulationFrame::Pixel => in_pixel_frame(aop_deg, row, col, params),
+                        _ => aop_deg,
+                    },
+                    dop: dop.into_inner(),
+                    scattering_deg,
+                }))
+            })
+            .collect::<Result<_>>()?;
 
-            Some(Ray::new(*coord, aop, dop))
+        Ok(SimulatedImage {
+            rows: rows.end - rows.start,
+            cols: params.image_cols(),
+            pixels,
+            skipped: skipped.into_inner(),
         })
-        .collect();
+    }
+}
+
+// Row and col of the i-th pixel in row-major order.
+// Both fit in a u16 since the row and col counts do.
+fn pixel_index(i: usize, cols: usize) -> (u16, u16) {
+    ((i / cols) as u16, (i % cols) as u16)
+}
 
-    Ok(RayImage::from_rays_with_sensor(rays, &image_sensor).expect("no ray hits the same pixel"))
+// Split the sensor rows into consecutive tiles of at most tile_rows each.
+fn tile_ranges(rows: u16, tile_rows: u16) -> impl Iterator<Item = Range<u16>> {
+    (0..rows)
+        .step_by(usize::from(tile_rows))
+        .map(move |start| start..start.saturating_add(tile_rows).min(rows))
 }
 
-fn parse_params(path: &PathBuf) -> Result<Params> {
-    let mut buffer = String::new();
-    std::fs::File::open(path)?.read_to_string(&mut buffer)?;
-    let params = toml::from_str(&buffer)?;
+// Move a sensor coordinate of an equidistant fisheye lens, imaging the bearing
+// at angle theta = r / f off the optical axis, to where the pinhole lens
+// images the same bearing at f * tan(theta). Angles of 90 degrees or more have
+// no pinhole image, so they return None.
+fn fisheye_to_rectilinear(
+    coord: Coordinate<CameraFrd>,
+    focal_length: Length,
+) -> Option<Coordinate<CameraFrd>> {
+    let focal_length_mm = focal_length.get::<millimeter>();
+    let x = coord.frd_right().get::<millimeter>() / focal_length_mm;
+    let y = coord.frd_down().get::<millimeter>() / focal_length_mm;
+    let theta = x.hypot(y);
+    if theta == 0. {
+        return Some(coord);
+    }
+    if theta >= std::f64::consts::FRAC_PI_2 {
+        return None;
+    }
 
-    Ok(params)
+    let scale = theta.tan() / theta;
+    Some(
+        Coordinate::<CameraFrd>::builder()
+            .frd_front(coord.frd_front())
+            .frd_right(coord.frd_right() * scale)
+            .frd_down(coord.frd_down() * scale)
+            .build(),
+    )
+}
+
+// Unit vector pointing from the observer to the sun in east, north, up components.
+fn sun_direction(params: &Params) -> Result<[f64; 3]> {
+    let solar_position = params.solar_position()?;
+    let azimuth = solar_position.azimuth.to_radians();
+    let zenith = solar_position.zenith_angle.to_radians();
+
+    Ok([
+        zenith.sin() * azimuth.sin(),
+        zenith.sin() * azimuth.cos(),
+        zenith.cos(),
+    ])
+}
+
+// Angle in degrees between a bearing, with its azimuth clockwise from north,
+// and the direction of the sun.
+fn scattering_angle_deg(azimuth: Angle, elevation: Angle, sun_enu: [f64; 3]) -> f64 {
+    let (azimuth, elevation) = (azimuth.get::<radian>(), elevation.get::<radian>());
+    let bearing_enu = [
+        elevation.cos() * azimuth.sin(),
+        elevation.cos() * azimuth.cos(),
+        elevation.sin(),
+    ];
+
+    let cos_angle: f64 = bearing_enu.iter().zip(sun_enu).map(|(b, s)| b * s).sum();
+    cos_angle.clamp(-1., 1.).acos().to_degrees()
+}
+
+// Measure an AoP from the direction pointing away from the image center
+// rather than from the meridian used by the sky model.
+fn in_pixel_frame(aop_deg: f64, row: u16, col: u16, params: &Params) -> f64 {
+    let center_row = f64::from(params.image_rows().saturating_sub(1)) / 2.;
+    let center_col = f64::from(params.image_cols().saturating_sub(1)) / 2.;
+
+    // Rows run downwards, so flip them for a counterclockwise angle.
+    // Scale by the pixel pitch so non-square pixels give the physical direction.
+    let up_um = (center_row - f64::from(row)) * params.pixel_size.pixel_height_um;
+    let right_um = (f64::from(col) - center_col) * params.pixel_size.pixel_width_um;
+    let radial_deg = up_um.atan2(right_um).to_degrees();
+    wrap_aop(aop_deg - radial_deg)
+}
+
+const UNDISTORT_ITERATIONS: usize = 20;
+
+// Move a sensor coordinate to where an ideal pinhole lens would image the
+// bearing that the distorted lens images at the coordinate.
+fn undistort(
+    coord: Coordinate<CameraFrd>,
+    distortion: &RadialDistortion,
+    focal_length: Length,
+) -> Coordinate<CameraFrd> {
+    let focal_length_mm = focal_length.get::<millimeter>();
+    let x = coord.frd_right().get::<millimeter>() / focal_length_mm;
+    let y = coord.frd_down().get::<millimeter>() / focal_length_mm;
+    let r_distorted = x.hypot(y);
+    if r_distorted == 0. {
+        return coord;
+    }
+
+    // Invert the distortion polynomial by fixed-point iteration.
+    let mut r = r_distorted;
+    for _ in 0..UNDISTORT_ITERATIONS {
+        let r2 = r * r;
+        r = r_distorted / (1. + distortion.k1 * r2 + distortion.k2 * r2 * r2);
+    }
+
+    let scale = r / r_distorted;
+    Coordinate::<CameraFrd>::builder()
+        .frd_front(coord.frd_front())
+        .frd_right(coord.frd_right() * scale)
+        .frd_down(coord.frd_down() * scale)
+        .build()
+}
+
+/// Simulated quantities for each pixel of the sensor in row-major order.
+pub(crate) struct SimulatedImage {
+    rows: u16,
+    cols: u16,
+    pixels: Vec<Option<Sample>>,
+    /// Pixels left missing without tracing, masked or outside the image circle.
+    skipped: usize,
+}
+
+#[derive(Copy, Clone)]
+struct Sample {
+    aop_deg: f64,
+    dop: f64,
+    scattering_deg: f64,
+}
+
+impl SimulatedImage {
+    // Number of pixels the sky model produced a sample for.
+    fn populated(&self) -> usize {
+        self.pixels.iter().flatten().count()
+    }
+
+    // Rearrange the pixels, swapping the dimensions for quarter turns.
+    fn transformed(self, transform: &Transform) -> Self {
+        let (rows, cols) = (usize::from(self.rows), usize::from(self.cols));
+        let (out_rows, out_cols) = match transform {
+            Transform::Rotate90 | Transform::Rotate270 => (self.cols, self.rows),
+            _ => (self.rows, self.cols),
+        };
+
+        let pixels = (0..usize::from(out_rows))
+            .flat_map(|row| (0..usize::from(out_cols)).map(move |col| (row, col)))
+            .map(|(row, col)| {
+                let (src_row, src_col) = match transform {
+                    Transform::Rotate90 => (rows - 1 - col, row),
+                    Transform::Rotate180 => (rows - 1 - row, cols - 1 - col),
+                    Transform::Rotate270 => (col, cols - 1 - row),
+                    Transform::FlipH => (row, cols - 1 - col),
+                    Transform::FlipV => (rows - 1 - row, col),
+                };
+                self.pixels[src_row * cols + src_col]
+            })
+            .collect();
+
+        Self {
+            rows: out_rows,
+            cols: out_cols,
+            pixels,
+            skipped: self.skipped,
+        }
+    }
+}
+
+/// Gaussian measurement noise added to the simulated samples.
+struct Noise {
+    aop_deg: Normal<f64>,
+    dop: Option<Normal<f64>>,
+    rng: StdRng,
+}
+
+impl Noise {
+    fn from_args(args: &SimulateArgs) -> Result<Option<Self>> {
+        if args.noise_std_deg.is_none() && args.noise_dop_std.is_none() {
+            return Ok(None);
+        }
+
+        let aop_deg = Normal::new(0., args.noise_std_deg.unwrap_or(0.))
+            .context("noise-std-deg is a finite, non-negative standard deviation")?;
+        let dop = args
+            .noise_dop_std
+            .map(|std| Normal::new(0., std))
+            .transpose()
+            .context("noise-dop-std is a finite, non-negative standard deviation")?;
+        let rng = match args.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_os_rng(),
+        };
+
+        Ok(Some(Self { aop_deg, dop, rng }))
+    }
+
+    // Perturb each sample, keeping the AoP and DoP on their valid intervals.
+    fn apply(&mut self, image: &mut SimulatedImage) {
+        for sample in image.pixels.iter_mut().flatten() {
+            sample.aop_deg = wrap_aop(sample.aop_deg + self.aop_deg.sample(&mut self.rng));
+            if let Some(dop) = &self.dop {
+                sample.dop = (sample.dop + dop.sample(&mut self.rng)).clamp(0., 1.);
+            }
+        }
+    }
+}
+
+// Wrap an angle in degrees onto the AoP interval [-90, 90).
+pub(crate) fn wrap_aop(aop_deg: f64) -> f64 {
+    (aop_deg + 90.).rem_euclid(180.) - 90.
 }
 
 fn write_image(
-    ray_image: RayImage<GlobalFrame>,
-    image_rows: u16,
-    image_cols: u16,
+    image: &SimulatedImage,
     target: &SimulationTarget,
     path: &PathBuf,
+    options: &OutputOptions,
 ) -> Result<()> {
-    // Map the values in the RayImage to RGB colours.
-    // Draw missing pixels as white.
-    let image: Vec<u8> = match target {
-        SimulationTarget::Aop => ray_image
-            .ray_pixels()
-            .flat_map(|pixel| match pixel {
-                Some(ray) => to_rgb(ray.aop().angle().get::<degree>(), -90.0, 90.0)
-                    .expect("aop in between -90 and 90"),
-                None => [255, 255, 255],
-            })
-            .collect(),
-        SimulationTarget::Dop => ray_image
-            .ray_pixels()
-            .flat_map(|pixel| match pixel {
-                Some(ray) => {
-                    to_rgb(ray.dop().into_inner(), 0.0, 1.0).expect("dop in between 0 and 1")
-                }
-                None => [255, 255, 255],
-            })
-            .collect(),
+    if options.bit_depth == BitDepth::Sixteen {
+        return write_gray16(image, target, path, options);
+    }
+
+    // Map the simulated values to RGB colours.
+    // Draw missing pixels and those outside the span with the configured missing color.
+    let (x_min, x_max) = color_range(target, options)?;
+    let (image_rows, image_cols) = (image.rows, image.cols);
+    let image: Vec<u8> = target_values(image, target, &options.angle_unit)
+        .into_iter()
+        .flat_map(|value| {
+            value
+                .and_then(|x| to_rgb(x, x_min, x_max, &options.colormap))
+                .unwrap_or(options.missing_color)
+        })
+        .collect();
+
+    let (image, image_cols) = if options.legend {
+        let image = legend::with_legend(
+            &image,
+            image_rows.into(),
+            image_cols.into(),
+            x_min,
+            x_max,
+            &options.colormap,
+        );
+        (image, u32::from(image_cols) + legend::legend_width())
+    } else {
+        (image, image_cols.into())
     };
 
     // Save the buffer of RGB pixels as a PNG.
-    image::save_buffer(
-        &path,
+    PngEncoder::new(create_output(path, true, options)?).write_image(
         &image,
-        image_cols.into(),
+        image_cols,
         image_rows.into(),
         image::ExtendedColorType::Rgb8,
     )?;
@@ -218,51 +973,565 @@ fn write_image(
     Ok(())
 }
 
+fn write_gray16(
+    image: &SimulatedImage,
+    target: &SimulationTarget,
+    path: &PathBuf,
+    options: &OutputOptions,
+) -> Result<()> {
+    let (image_rows, image_cols) = (image.rows, image.cols);
+    // Scale the values linearly from 1 to the top of the u16 range.
+    // Zero is reserved for missing pixels and those outside the span.
+    let (x_min, x_max) = color_range(target, options)?;
+    let image: Vec<u8> = target_values(image, target, &options.angle_unit)
+        .into_iter()
+        .flat_map(|value| {
+            value
+                .filter(|x| (x_min..=x_max).contains(x))
+                .map(|x| {
+                    let x_norm = (x - x_min) / (x_max - x_min);
+                    (1. + x_norm * f64::from(u16::MAX - 1)).round() as u16
+                })
+                .unwrap_or(0)
+                .to_be_bytes()
+        })
+        .collect();
+
+    // Save the buffer as a PNG, recording the scale so values can be recovered.
+    let mut encoder = png::Encoder::new(
+        create_output(path, true, options)?,
+        image_cols.into(),
+        image_rows.into(),
+    );
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Sixteen);
+    encoder.add_text_chunk("min".to_string(), x_min.to_string())?;
+    encoder.add_text_chunk("max".to_string(), x_max.to_string())?;
+    encoder.add_text_chunk(
+        "mapping".to_string(),
+        "value = min + (pixel - 1) / 65534 * (max - min), pixel 0 is missing".to_string(),
+    )?;
+    encoder.write_header()?.write_image_data(&image)?;
+
+    Ok(())
+}
+
 fn write_dat(
-    ray_image: RayImage<GlobalFrame>,
-    rows: u16,
-    cols: u16,
+    image: &SimulatedImage,
     target: &SimulationTarget,
     path: &PathBuf,
+    options: &OutputOptions,
 ) -> Result<()> {
-    let image: Vec<f64> = match target {
-        SimulationTarget::Aop => ray_image
-            .ray_pixels()
-            .map(|pixel| match pixel {
-                Some(ray) => ray.aop().angle().get::<degree>(),
-                None => f64::NAN,
-            })
-            .collect(),
-        SimulationTarget::Dop => ray_image
-            .ray_pixels()
-            .map(|pixel| match pixel {
-                Some(ray) => ray.dop().into_inner(),
-                None => f64::NAN,
-            })
-            .collect(),
-    };
+    // Write the header first so a clobbered header leaves no empty data file behind.
+    if options.envi {
+        write_envi_header(path, image.rows, image.cols, target, options)?;
+        let mut output_file = create_output(path, true, options)?;
+        return write_envi_rows(&mut output_file, image, target, options);
+    }
 
     // Write simulated output to file.
-    let mut output_file = BufWriter::new(File::create(&path)?);
+    let mut output_file = create_output(path, false, options)?;
+    write_dat_header(&mut output_file, target, options)?;
+    write_dat_rows(&mut output_file, image, target, options)
+}
+
+// Describe the raw binary dat in an ENVI header next to it, e.g. out.dat -> out.hdr.
+fn write_envi_header(
+    path: &PathBuf,
+    rows: u16,
+    cols: u16,
+    target: &SimulationTarget,
+    options: &OutputOptions,
+) -> Result<()> {
+    if is_stdout(path) {
+        anyhow::bail!("ENVI outputs need a file path for the header");
+    }
+
+    let names: Vec<&str> = channels(target, options).iter().map(channel_name).collect();
+    let path = path.with_extension("hdr");
+    check_clobber(&path, options.no_clobber)?;
+    let mut header_file = BufWriter::new(File::create(&path)?);
+    writeln!(header_file, "ENVI")?;
+    writeln!(header_file, "description = {{rumpus_cli simulation}}")?;
+    writeln!(header_file, "samples = {cols}")?;
+    writeln!(header_file, "lines = {rows}")?;
+    writeln!(header_file, "bands = {}", names.len())?;
+    writeln!(header_file, "header offset = 0")?;
+    writeln!(header_file, "file type = ENVI Standard")?;
+    // 5 is double precision float, byte order 0 is little-endian.
+    writeln!(header_file, "data type = 5")?;
+    // Channels are adjacent for each pixel, like the ASCII dat.
+    writeln!(header_file, "interleave = bip")?;
+    writeln!(header_file, "byte order = 0")?;
+    writeln!(header_file, "band names = {{{}}}", names.join(", "))?;
+    if let Some(missing) = options.missing_value {
+        writeln!(header_file, "data ignore value = {missing}")?;
+    }
+
+    Ok(())
+}
+
+fn write_envi_rows(
+    writer: &mut impl Write,
+    image: &SimulatedImage,
+    target: &SimulationTarget,
+    options: &OutputOptions,
+) -> Result<()> {
+    let missing = options.missing_value.unwrap_or(f64::NAN);
+    let channels = channel_values(image, target, options);
+
+    for i in 0..image.pixels.len() {
+        for values in &channels {
+            writer.write_all(&values[i].unwrap_or(missing).to_le_bytes())?;
+        }
+    }
+
+    Ok(())
+}
+
+// Describe the layout of multi-channel outputs in a comment line.
+fn write_dat_header(
+    writer: &mut impl Write,
+    target: &SimulationTarget,
+    options: &OutputOptions,
+) -> Result<()> {
+    if !options.channels.is_empty() {
+        let names: Vec<&str> = channels(target, options).iter().map(channel_name).collect();
+        writeln!(writer, "# channels per pixel: {}", names.join(" "))?;
+    }
+
+    Ok(())
+}
+
+fn write_dat_rows(
+    writer: &mut impl Write,
+    image: &SimulatedImage,
+    target: &SimulationTarget,
+    options: &OutputOptions,
+) -> Result<()> {
+    let (rows, cols) = (image.rows, image.cols);
+    let missing = options.missing_value.unwrap_or(f64::NAN);
+    let channels = channel_values(image, target, options);
+
     for row in 0..rows {
         for col in 0..cols {
-            let i: usize = (row * cols + col).try_into()?;
-            write!(output_file, "{:5} ", image[i])?;
+            let i = usize::from(row) * usize::from(cols) + usize::from(col);
+            for values in &channels {
+                write!(writer, "{:5} ", values[i].unwrap_or(missing))?;
+            }
         }
-        write!(output_file, "\n")?;
+        writeln!(writer)?;
+    }
+
+    Ok(())
+}
+
+fn write_csv(
+    image: &SimulatedImage,
+    target: &SimulationTarget,
+    path: &PathBuf,
+    options: &OutputOptions,
+) -> Result<()> {
+    // Write simulated output to file.
+    let mut output_file = create_output(path, false, options)?;
+    if options.header {
+        write_csv_header(&mut output_file, image.cols, target, options)?;
+    }
+    write_csv_rows(&mut output_file, image, 0, target, options)
+}
+
+// Columns are named by pixel index, with the channel appended for multi-channel outputs.
+fn write_csv_header(
+    writer: &mut impl Write,
+    cols: u16,
+    target: &SimulationTarget,
+    options: &OutputOptions,
+) -> Result<()> {
+    write!(writer, "row")?;
+    for col in 0..cols {
+        if options.channels.is_empty() {
+            write!(writer, ",{col}")?;
+        } else {
+            for channel in channels(target, options) {
+                write!(writer, ",{col}_{}", channel_name(&channel))?;
+            }
+        }
+    }
+    writeln!(writer)?;
+
+    Ok(())
+}
+
+// Missing pixels are left as empty fields.
+fn write_csv_rows(
+    writer: &mut impl Write,
+    image: &SimulatedImage,
+    first_row: u16,
+    target: &SimulationTarget,
+    options: &OutputOptions,
+) -> Result<()> {
+    let cols = usize::from(image.cols);
+    let channels = channel_values(image, target, options);
+
+    for row in 0..usize::from(image.rows) {
+        let fields: Vec<String> = (row * cols..(row + 1) * cols)
+            .flat_map(|i| channels.iter().map(move |values| values[i]))
+            .map(|value| value.map(|value| value.to_string()).unwrap_or_default())
+            .collect();
+
+        if options.header {
+            write!(writer, "{},", usize::from(first_row) + row)?;
+        }
+        writeln!(writer, "{}", fields.join(","))?;
+    }
+
+    Ok(())
+}
+
+// The quantities written for each pixel, defaulting to just the target.
+fn channels(target: &SimulationTarget, options: &OutputOptions) -> Vec<SimulationTarget> {
+    match options.channels.is_empty() {
+        true => vec![*target],
+        false => options.channels.clone(),
+    }
+}
+
+fn channel_values(
+    image: &SimulatedImage,
+    target: &SimulationTarget,
+    options: &OutputOptions,
+) -> Vec<Vec<Option<f64>>> {
+    channels(target, options)
+        .iter()
+        .map(|channel| target_values(image, channel, &options.angle_unit))
+        .collect()
+}
+
+fn channel_name(channel: &SimulationTarget) -> &'static str {
+    match channel {
+        SimulationTarget::Aop => "aop",
+        SimulationTarget::Dop => "dop",
+        SimulationTarget::Q => "q",
+        SimulationTarget::U => "u",
+        SimulationTarget::ScatteringAngle => "scattering_angle",
+    }
+}
+
+// Length of one FITS header card and of one FITS block.
+const FITS_CARD: usize = 80;
+const FITS_BLOCK: usize = 2880;
+
+fn write_fits(
+    image: &SimulatedImage,
+    params: &Params,
+    target: &SimulationTarget,
+    path: &PathBuf,
+    options: &OutputOptions,
+) -> Result<()> {
+    let rows = image.rows.to_string();
+    let cols = image.cols.to_string();
+    let real = |value: f64| format!("{value:?}");
+    let angle_unit = match options.angle_unit {
+        AngleUnit::Degrees => "deg",
+        AngleUnit::Radians => "rad",
+    };
+    let (unit, description) = match target {
+        SimulationTarget::Aop => (angle_unit, "angle of polarization"),
+        SimulationTarget::Dop => ("", "degree of polarization"),
+        SimulationTarget::Q => ("", "normalized Stokes Q"),
+        SimulationTarget::U => ("", "normalized Stokes U"),
+        SimulationTarget::ScatteringAngle => (angle_unit, "scattering angle"),
+    };
+    let extname = channel_name(target).to_uppercase();
+    let date_obs = params.time().format("%Y-%m-%dT%H:%M:%S").to_string();
+
+    // The primary HDU holds the target along with the simulation metadata.
+    let mut header = vec![
+        fits_card("SIMPLE", "T", "conforms to the FITS standard"),
+        fits_card("BITPIX", "-64", "IEEE double precision"),
+        fits_card("NAXIS", "2", ""),
+        fits_card("NAXIS1", &cols, "image columns"),
+        fits_card("NAXIS2", &rows, "image rows"),
+        fits_card("EXTEND", "T", ""),
+        fits_card("COMMENT", "The first data row is the top sensor row.", ""),
+        fits_card("EXTNAME", &fits_string(&extname), description),
+    ];
+    if !unit.is_empty() {
+        header.push(fits_card("BUNIT", &fits_string(unit), ""));
+    }
+    header.extend([
+        fits_card("DATE-OBS", &fits_string(&date_obs), "UTC"),
+        fits_card("LAT", &real(params.lat_deg), "observer latitude [deg]"),
+        fits_card("LON", &real(params.lon_deg), "observer longitude [deg]"),
+        fits_card("ALT", &real(params.alt_m), "observer altitude [m]"),
+        fits_card(
+            "FOCALLEN",
+            &real(params.focal_length_mm),
+            "focal length [mm]",
+        ),
+        fits_card(
+            "PIXWIDTH",
+            &real(params.pixel_size.pixel_width_um),
+            "pixel width [um]",
+        ),
+        fits_card(
+            "PIXHEIGH",
+            &real(params.pixel_size.pixel_height_um),
+            "pixel height [um]",
+        ),
+        fits_card("YAW", &real(params.yaw_deg), "camera yaw [deg]"),
+        fits_card("PITCH", &real(params.pitch_deg), "camera pitch [deg]"),
+        fits_card("ROLL", &real(params.roll_deg), "camera roll [deg]"),
+    ]);
+
+    // The DoP of an AoP follows in an image extension.
+    let mut extension = vec![
+        fits_card("XTENSION", &fits_string("IMAGE"), "image extension"),
+        fits_card("BITPIX", "-64", "IEEE double precision"),
+        fits_card("NAXIS", "2", ""),
+        fits_card("NAXIS1", &cols, "image columns"),
+        fits_card("NAXIS2", &rows, "image rows"),
+        fits_card("PCOUNT", "0", ""),
+        fits_card("GCOUNT", "1", ""),
+        fits_card("EXTNAME", &fits_string("DOP"), "degree of polarization"),
+    ];
+
+    let mut hdus = vec![(&mut header, *target)];
+    if *target == SimulationTarget::Aop {
+        hdus.push((&mut extension, SimulationTarget::Dop));
+    }
+
+    let mut output_file = create_output(path, true, options)?;
+    for (cards, target) in hdus {
+        cards.push(fits_card("END", "", ""));
+        write_fits_header(&mut output_file, cards)?;
+
+        let values = target_values(image, &target, &options.angle_unit);
+        write_fits_data(&mut output_file, &values)?;
+    }
+
+    Ok(())
+}
+
+// Format a single 80 character header card with a fixed-format value.
+fn fits_card(keyword: &str, value: &str, comment: &str) -> String {
+    let mut card = match keyword {
+        "END" => keyword.to_string(),
+        // Commentary cards carry free text in place of a value.
+        "COMMENT" => format!("{keyword:<8}{value}"),
+        _ if value.starts_with('\'') => format!("{keyword:<8}= {value:<20}"),
+        _ => format!("{keyword:<8}= {value:>20}"),
+    };
+    if !comment.is_empty() {
+        card.push_str(" / ");
+        card.push_str(comment);
+    }
+
+    card.truncate(FITS_CARD);
+    format!("{card:<FITS_CARD$}")
+}
+
+// Quote a string value, padding it to the minimum of eight characters.
+fn fits_string(value: &str) -> String {
+    format!("'{value:<8}'")
+}
+
+fn write_fits_header(writer: &mut impl Write, cards: &[String]) -> Result<()> {
+    let mut header = cards.concat();
+    let padding = header.len().next_multiple_of(FITS_BLOCK) - header.len();
+    header.push_str(&" ".repeat(padding));
+    writer.write_all(header.as_bytes())?;
+
+    Ok(())
+}
+
+fn write_fits_data(writer: &mut impl Write, image: &[Option<f64>]) -> Result<()> {
+    // Rows are stored in sensor order like every other format, so viewers
+    // like DS9 that draw the first row at the bottom show the image flipped.
+    let mut size = 0;
+    for value in image {
+        writer.write_all(&value.unwrap_or(f64::NAN).to_be_bytes())?;
+        size += size_of::<f64>();
+    }
+
+    let padding = size.next_multiple_of(FITS_BLOCK) - size;
+    writer.write_all(&vec![0; padding])?;
+
+    Ok(())
+}
+
+const NPY_MAGIC: &[u8] = b"\x93NUMPY";
+
+fn write_npy(
+    image: &SimulatedImage,
+    target: &SimulationTarget,
+    path: &PathBuf,
+    options: &OutputOptions,
+) -> Result<()> {
+    let (rows, cols) = (image.rows, image.cols);
+    let missing = options.missing_value.unwrap_or(f64::NAN);
+
+    // Version 1.0 header describing a C-ordered array of little-endian f64.
+    // The header is padded with spaces so the data starts on a 64 byte boundary.
+    let mut header =
+        format!("{{'descr': '<f8', 'fortran_order': False, 'shape': ({rows}, {cols}), }}");
+    let preamble = NPY_MAGIC.len() + 2 + size_of::<u16>();
+    let unpadded = preamble + header.len() + 1;
+    header.push_str(&" ".repeat(unpadded.next_multiple_of(64) - unpadded));
+    header.push('\n');
+
+    let mut output_file = create_output(path, true, options)?;
+    output_file.write_all(NPY_MAGIC)?;
+    output_file.write_all(&[1, 0])?;
+    output_file.write_all(&u16::try_from(header.len())?.to_le_bytes())?;
+    output_file.write_all(header.as_bytes())?;
+
+    for value in target_values(image, target, &options.angle_unit) {
+        output_file.write_all(&value.unwrap_or(missing).to_le_bytes())?;
+    }
+
+    Ok(())
+}
+
+// Physical range of the target quantity.
+fn target_range(target: &SimulationTarget, unit: &AngleUnit) -> (f64, f64) {
+    match target {
+        SimulationTarget::Aop => (in_unit(-90.0, unit), in_unit(90.0, unit)),
+        SimulationTarget::Dop => (0.0, 1.0),
+        SimulationTarget::Q | SimulationTarget::U => (-1.0, 1.0),
+        SimulationTarget::ScatteringAngle => (0.0, in_unit(180.0, unit)),
+    }
+}
+
+// GeoTIFF tags describing the observer position, see the GeoTIFF 1.0 spec.
+const MODEL_TIEPOINT_TAG: u16 = 33922;
+const GEO_KEY_DIRECTORY_TAG: u16 = 34735;
+
+fn write_geotiff(
+    image: &SimulatedImage,
+    params: &Params,
+    target: &SimulationTarget,
+    path: &PathBuf,
+    options: &OutputOptions,
+) -> Result<()> {
+    // The TIFF encoder seeks back to patch offsets, so it needs a real file.
+    if is_stdout(path) {
+        anyhow::bail!("GeoTIFF outputs cannot be written to stdout");
+    }
+
+    let missing = options.missing_value.unwrap_or(f64::NAN);
+    let values: Vec<f64> = target_values(image, target, &options.angle_unit)
+        .into_iter()
+        .map(|value| value.unwrap_or(missing))
+        .collect();
+
+    check_clobber(path, options.no_clobber)?;
+    let mut encoder = TiffEncoder::new(BufWriter::new(File::create(path)?))?;
+    let mut tiff =
+        encoder.new_image::<colortype::Gray64Float>(image.cols.into(), image.rows.into())?;
+
+    // Tie the first pixel to the observer in geographic WGS84 coordinates.
+    // The pixels look at the sky rather than the ground, so no pixel scale or
+    // transformation is embedded and GDAL reports the tiepoint as a GCP
+    // without a geotransform.
+    let geo_keys: [u16; 16] = [
+        1, 1, 0, 3, // version 1.1.0 with three keys
+        1024, 0, 1, 2, // GTModelTypeGeoKey: geographic
+        1025, 0, 1, 1, // GTRasterTypeGeoKey: pixel is area
+        2048, 0, 1, 4326, // GeographicTypeGeoKey: WGS84
+    ];
+    let tiepoint = [0., 0., 0., params.lon_deg, params.lat_deg, params.alt_m];
+    let date_time = params.time().format("%Y:%m:%d %H:%M:%S").to_string();
+
+    let encoder = tiff.encoder();
+    encoder.write_tag(Tag::Unknown(GEO_KEY_DIRECTORY_TAG), &geo_keys[..])?;
+    encoder.write_tag(Tag::Unknown(MODEL_TIEPOINT_TAG), &tiepoint[..])?;
+    encoder.write_tag(Tag::DateTime, date_time.as_str())?;
+    tiff.write_data(&values)?;
+
+    Ok(())
+}
+
+// Reject conflicting output options before anything is traced.
+fn check_output_options(options: &OutputOptions) -> Result<()> {
+    color_range(&SimulationTarget::Aop, options)?;
+    if options.legend && options.bit_depth == BitDepth::Sixteen {
+        anyhow::bail!("a legend can only be drawn on 8-bit outputs");
     }
 
     Ok(())
 }
 
+// Span of values covered by the colormap in image outputs.
+fn color_range(target: &SimulationTarget, options: &OutputOptions) -> Result<(f64, f64)> {
+    if *target != SimulationTarget::Aop {
+        return Ok(target_range(target, &options.angle_unit));
+    }
+
+    let (aop_min, aop_max) = (options.aop_min, options.aop_max);
+    if aop_min >= aop_max {
+        anyhow::bail!("aop-min {aop_min} must be less than aop-max {aop_max}");
+    }
+    if aop_min < -90. || aop_max > 90. {
+        anyhow::bail!("aop-min and aop-max must be between -90 and 90 degrees");
+    }
+
+    Ok((
+        in_unit(aop_min, &options.angle_unit),
+        in_unit(aop_max, &options.angle_unit),
+    ))
+}
+
+// Extract the target quantity from each pixel in row-major order.
+fn target_values(
+    image: &SimulatedImage,
+    target: &SimulationTarget,
+    unit: &AngleUnit,
+) -> Vec<Option<f64>> {
+    image
+        .pixels
+        .iter()
+        .map(|pixel| {
+            pixel.map(|sample| match target {
+                SimulationTarget::Aop => in_unit(sample.aop_deg, unit),
+                SimulationTarget::Dop => sample.dop,
+                // Normalized linear Stokes parameters.
+                SimulationTarget::Q => sample.dop * (2. * sample.aop_deg).to_radians().cos(),
+                SimulationTarget::U => sample.dop * (2. * sample.aop_deg).to_radians().sin(),
+                SimulationTarget::ScatteringAngle => in_unit(sample.scattering_deg, unit),
+            })
+        })
+        .collect()
+}
+
+// Express an angle given in degrees in the configured unit.
+fn in_unit(angle_deg: f64, unit: &AngleUnit) -> f64 {
+    match unit {
+        AngleUnit::Degrees => angle_deg,
+        AngleUnit::Radians => angle_deg.to_radians(),
+    }
+}
+
 // Map an f64 on the interval [x_min, x_max] to an RGB color.
-pub fn to_rgb(x: f64, x_min: f64, x_max: f64) -> Option<[u8; 3]> {
+pub fn to_rgb(x: f64, x_min: f64, x_max: f64, colormap: &Colormap) -> Option<[u8; 3]> {
     if x < x_min || x > x_max {
         return None;
     }
 
     let interval_width = x_max - x_min;
-    let x_norm = ((x - x_min) / interval_width * 255.).floor() as u8;
+    let x_norm = (x - x_min) / interval_width;
+
+    Some(match colormap {
+        Colormap::Jet => jet(x_norm),
+        Colormap::Viridis => viridis(x_norm),
+        Colormap::Grayscale => grayscale(x_norm),
+        Colormap::Hsv => hsv(x_norm),
+    })
+}
+
+// Jet-like colormap built from clamped linear ramps in each channel.
+fn jet(x_norm: f64) -> [u8; 3] {
+    let x_norm = (x_norm * 255.).floor() as u8;
 
     let r = vec![
         255,
@@ -315,5 +1584,446 @@ pub fn to_rgb(x: f64, x_min: f64, x_max: f64) -> Option<[u8; 3]> {
     .min()
     .unwrap();
 
-    Some([r, g, b])
+    [r, g, b]
+}
+
+// Perceptually uniform colormap interpolated between samples of viridis.
+fn viridis(x_norm: f64) -> [u8; 3] {
+    const SAMPLES: [[f64; 3]; 9] = [
+        [68., 1., 84.],
+        [71., 44., 122.],
+        [59., 81., 139.],
+        [44., 113., 142.],
+        [33., 144., 141.],
+        [39., 173., 129.],
+        [92., 200., 99.],
+        [170., 220., 50.],
+        [253., 231., 37.],
+    ];
+
+    let position = x_norm * (SAMPLES.len() - 1) as f64;
+    let lower = (position.floor() as usize).min(SAMPLES.len() - 2);
+    let t = position - lower as f64;
+
+    std::array::from_fn(|channel| {
+        let a = SAMPLES[lower][channel];
+        let b = SAMPLES[lower + 1][channel];
+        (a + (b - a) * t).round() as u8
+    })
+}
+
+fn grayscale(x_norm: f64) -> [u8; 3] {
+    let level = (x_norm * 255.).round() as u8;
+    [level, level, level]
+}
+
+// Cyclic colormap sweeping the hue at full saturation and value.
+// Both ends of the interval map to red, which suits angular data like AoP.
+fn hsv(x_norm: f64) -> [u8; 3] {
+    let hue = (x_norm * 6.) % 6.;
+    let f = hue.fract();
+    let (r, g, b) = match hue as u8 {
+        0 => (1., f, 0.),
+        1 => (1. - f, 1., 0.),
+        2 => (0., 1., f),
+        3 => (0., 1. - f, 1.),
+        4 => (f, 0., 1.),
+        _ => (1., 0., 1. - f),
+    };
+
+    [r, g, b].map(|c: f64| (c * 255.).round() as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::params::PixelSize;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct TestCli {
+        #[command(flatten)]
+        options: OutputOptions,
+    }
+
+    fn default_options() -> OutputOptions {
+        TestCli::parse_from(["test"]).options
+    }
+
+    #[derive(Parser)]
+    struct TestSimulateCli {
+        #[command(flatten)]
+        args: SimulateArgs,
+    }
+
+    fn simulate_args(args: &[&str]) -> SimulateArgs {
+        TestSimulateCli::parse_from(["test"].iter().chain(args)).args
+    }
+
+    fn sample(aop_deg: f64) -> Option<Sample> {
+        Some(Sample {
+            aop_deg,
+            dop: 0.5,
+            scattering_deg: 90.,
+        })
+    }
+
+    // A wide fisheye on a tiny sensor, seeing a whole hemisphere in few pixels.
+    fn small_params() -> Params {
+        Params {
+            pixel_size: PixelSize {
+                pixel_width_um: 500.,
+                pixel_height_um: 500.,
+            },
+            image_rows: 80,
+            image_cols: 80,
+            ..Params::default()
+        }
+    }
+
+    fn trace(params: &Params, projection: &Projection) -> SimulatedImage {
+        let camera = Camera::new(params.lens().unwrap(), params.orientation());
+        let tracer = Tracer {
+            params,
+            image_sensor: &params.image_sensor(),
+            camera: &camera,
+            sky_model: &params.sky_model().unwrap(),
+            frame: &SimulationFrame::Meridian,
+            projection,
+            mask: None,
+        };
+        tracer
+            .trace(0..params.image_rows(), &ProgressBar::hidden())
+            .unwrap()
+    }
+
+    #[test]
+    fn dop_peaks_perpendicular_to_the_sun() {
+        // Look along each axis so some view contains the sun.
+        let mut samples = Vec::new();
+        for pitch_deg in [-90., 0., 90.] {
+            for yaw_deg in [0., 90., 180., 270.] {
+                let params = Params {
+                    yaw_deg,
+                    pitch_deg,
+                    ..small_params()
+                };
+                let image = trace(&params, &Projection::FisheyeEquidistant);
+                samples.extend(image.pixels.into_iter().flatten());
+            }
+        }
+
+        let peak = samples
+            .iter()
+            .max_by(|a, b| a.dop.total_cmp(&b.dop))
+            .unwrap();
+        assert!(
+            (peak.scattering_deg - 90.).abs() < 10.,
+            "peak at {} deg",
+            peak.scattering_deg
+        );
+
+        let along_sun: Vec<&Sample> = samples
+            .iter()
+            .filter(|sample| sample.scattering_deg < 10. || sample.scattering_deg > 170.)
+            .collect();
+        assert!(!along_sun.is_empty());
+        for sample in along_sun {
+            assert!(
+                sample.dop < 0.1 * peak.dop,
+                "dop {} at {} deg",
+                sample.dop,
+                sample.scattering_deg
+            );
+        }
+    }
+
+    #[test]
+    fn jet_endpoints_and_midpoint() {
+        assert_eq!(jet(0.), [0, 0, 255]);
+        assert_eq!(jet(0.5), [124, 255, 131]);
+        assert_eq!(jet(1.), [131, 0, 0]);
+    }
+
+    #[test]
+    fn viridis_endpoints_and_midpoint() {
+        assert_eq!(viridis(0.), [68, 1, 84]);
+        assert_eq!(viridis(0.5), [33, 144, 141]);
+        assert_eq!(viridis(1.), [253, 231, 37]);
+    }
+
+    #[test]
+    fn grayscale_endpoints_and_midpoint() {
+        assert_eq!(grayscale(0.), [0, 0, 0]);
+        assert_eq!(grayscale(0.5), [128, 128, 128]);
+        assert_eq!(grayscale(1.), [255, 255, 255]);
+    }
+
+    #[test]
+    fn hsv_endpoints_and_midpoint() {
+        assert_eq!(hsv(0.), [255, 0, 0]);
+        assert_eq!(hsv(0.5), [0, 255, 255]);
+        assert_eq!(hsv(1.), [255, 0, 0]);
+    }
+
+    #[test]
+    fn hsv_is_cyclic() {
+        assert_eq!(hsv(0.), hsv(1.));
+    }
+
+    #[test]
+    fn to_rgb_rejects_values_outside_the_range() {
+        assert_eq!(to_rgb(-91., -90., 90., &Colormap::Jet), None);
+        assert_eq!(to_rgb(91., -90., 90., &Colormap::Jet), None);
+        assert_eq!(
+            to_rgb(0., -90., 90., &Colormap::Grayscale),
+            Some([128, 128, 128])
+        );
+    }
+
+    #[test]
+    fn output_paths_include_every_sidecar() {
+        let args = simulate_args(&[
+            "-t",
+            "aop",
+            "-o",
+            "out.dat",
+            "--envi",
+            "--stokes",
+            "--metadata",
+            "--dop-output",
+            "dop.png",
+        ]);
+        let output = args.output.clone().unwrap();
+        let paths = output_paths(&args, &output, "_0001").unwrap();
+        let expected = [
+            "out_0001.hdr",
+            "out_0001.dat",
+            "dop_0001.png",
+            "out_0001_q.hdr",
+            "out_0001_q.dat",
+            "out_0001_u.hdr",
+            "out_0001_u.dat",
+            "out_0001.json",
+        ];
+        assert_eq!(paths, expected.map(PathBuf::from));
+    }
+
+    #[test]
+    fn pixel_frame_follows_the_physical_direction_of_non_square_pixels() {
+        let params = Params {
+            pixel_size: PixelSize {
+                pixel_width_um: 2.,
+                pixel_height_um: 1.,
+            },
+            image_rows: 3,
+            image_cols: 3,
+            ..Params::default()
+        };
+        // One pixel up and right of the center is 1 um up and 2 um right.
+        let radial_deg = 1f64.atan2(2.).to_degrees();
+        assert!(in_pixel_frame(radial_deg, 0, 2, &params).abs() < 1e-9);
+    }
+
+    #[test]
+    fn distorted_samples_stay_at_their_own_pixel() {
+        let distortion = RadialDistortion { k1: -0.5, k2: 0. };
+        let params = Params {
+            pixel_size: PixelSize {
+                pixel_width_um: 100.,
+                pixel_height_um: 100.,
+            },
+            image_rows: 40,
+            image_cols: 40,
+            distortion: Some(distortion.clone()),
+            ..Params::default()
+        };
+        let image = trace(&params, &Projection::Rectilinear);
+
+        let image_sensor = params.image_sensor();
+        let camera = Camera::new(params.lens().unwrap(), params.orientation());
+        let sky_model = params.sky_model().unwrap();
+        for (row, col) in [(0, 0), (0, 39), (20, 20), (39, 0), (39, 39)] {
+            let coord = image_sensor
+                .at_pixel(row, col)
+                .context("on sensor")
+                .unwrap();
+            let coord = undistort(coord, &distortion, params.focal_length());
+            let bearing = camera.trace_from_sensor(coord).context("trace").unwrap();
+            let expected = sky_model
+                .aop(bearing)
+                .map(|aop| aop.angle().get::<degree>());
+
+            let i = usize::from(row) * 40 + usize::from(col);
+            let actual = image.pixels[i].map(|sample| sample.aop_deg);
+            assert_eq!(actual.is_some(), expected.is_some(), "row {row} col {col}");
+            if let (Some(actual), Some(expected)) = (actual, expected) {
+                assert!((actual - expected).abs() < 1e-9, "row {row} col {col}");
+            }
+        }
+    }
+
+    #[test]
+    fn tiled_output_matches_the_whole_image() {
+        let params = Params {
+            image_rows: 10,
+            image_cols: 8,
+            ..small_params()
+        };
+        let camera = Camera::new(params.lens().unwrap(), params.orientation());
+        let tracer = Tracer {
+            params: &params,
+            image_sensor: &params.image_sensor(),
+            camera: &camera,
+            sky_model: &params.sky_model().unwrap(),
+            frame: &SimulationFrame::Meridian,
+            projection: &Projection::FisheyeEquidistant,
+            mask: None,
+        };
+
+        for extension in ["dat", "csv"] {
+            let dir = std::env::temp_dir();
+            let whole = dir.join(format!(
+                "rumpus_cli_{}_whole.{extension}",
+                std::process::id()
+            ));
+            let tiled = dir.join(format!(
+                "rumpus_cli_{}_tiled.{extension}",
+                std::process::id()
+            ));
+            let args = simulate_args(&["-t", "aop", "-o", &tiled.to_string_lossy()]);
+
+            let image = tracer
+                .trace(0..params.image_rows(), &ProgressBar::hidden())
+                .unwrap();
+            write_output(
+                &image,
+                &params,
+                &args.target,
+                &whole,
+                &None,
+                &args.output_options,
+            )
+            .unwrap();
+            write_tiled(&tracer, &args, &mut None, &tiled, 3).unwrap();
+
+            let (whole_bytes, tiled_bytes) = (
+                std::fs::read(&whole).unwrap(),
+                std::fs::read(&tiled).unwrap(),
+            );
+            std::fs::remove_file(&whole).unwrap();
+            std::fs::remove_file(&tiled).unwrap();
+            assert_eq!(whole_bytes, tiled_bytes, "{extension}");
+        }
+    }
+
+    #[test]
+    fn output_options_are_checked_up_front() {
+        assert!(check_output_options(&default_options()).is_ok());
+
+        let parse = |args: &[&str]| TestCli::parse_from(["test"].iter().chain(args)).options;
+        let inverted = parse(&["--aop-min", "10", "--aop-max", "-10"]);
+        assert!(check_output_options(&inverted).is_err());
+        let legend_16 = parse(&["--legend", "--bit-depth", "16"]);
+        assert!(check_output_options(&legend_16).is_err());
+    }
+
+    #[test]
+    fn pixel_index_is_row_major() {
+        let cols = 3;
+        let pixels: Vec<(u16, u16)> = (0..6).map(|i| pixel_index(i, cols)).collect();
+        assert_eq!(pixels, [(0, 0), (0, 1), (0, 2), (1, 0), (1, 1), (1, 2)]);
+    }
+
+    #[test]
+    fn tiles_cover_the_same_pixels_as_the_whole_sensor() {
+        let (rows, cols) = (7, 5);
+        for tile_rows in [1, 2, 3, 7, 100] {
+            let tiled: Vec<usize> = tile_ranges(rows, tile_rows)
+                .flat_map(|tile| usize::from(tile.start) * cols..usize::from(tile.end) * cols)
+                .collect();
+            let untiled: Vec<usize> = (0..usize::from(rows) * cols).collect();
+            assert_eq!(tiled, untiled, "tile_rows {tile_rows}");
+        }
+    }
+
+    #[test]
+    fn fits_primary_hdu_holds_the_target_in_sensor_order() {
+        let image = SimulatedImage {
+            rows: 2,
+            cols: 3,
+            pixels: vec![
+                sample(-90.),
+                sample(-45.),
+                None,
+                sample(0.),
+                sample(45.),
+                sample(90.),
+            ],
+            skipped: 0,
+        };
+        let path = std::env::temp_dir().join(format!("rumpus_cli_{}.fits", std::process::id()));
+        let options = default_options();
+        write_fits(
+            &image,
+            &Params::default(),
+            &SimulationTarget::Q,
+            &path,
+            &options,
+        )
+        .unwrap();
+
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // A single header block and a single data block, without a DoP extension.
+        assert_eq!(bytes.len(), 2 * FITS_BLOCK);
+        let header = std::str::from_utf8(&bytes[..FITS_BLOCK]).unwrap();
+        assert!(header.contains("EXTNAME = 'Q       '"));
+        assert!(!header.contains("'DOP"));
+
+        let value = |i: usize| {
+            let start = FITS_BLOCK + i * size_of::<f64>();
+            f64::from_be_bytes(bytes[start..start + 8].try_into().unwrap())
+        };
+        assert!((value(0) + 0.5).abs() < 1e-12);
+        assert!(value(2).is_nan());
+        assert!((value(3) - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn npy_round_trip() {
+        let image = SimulatedImage {
+            rows: 2,
+            cols: 3,
+            pixels: vec![
+                sample(-90.),
+                sample(-45.5),
+                None,
+                sample(0.),
+                sample(30.25),
+                sample(89.),
+            ],
+            skipped: 0,
+        };
+        let path = std::env::temp_dir().join(format!("rumpus_cli_{}.npy", std::process::id()));
+        write_npy(&image, &SimulationTarget::Aop, &path, &default_options()).unwrap();
+
+        let bytes = std::fs::read(&path).unwrap();
+        let array = crate::compare::read_npy(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // The data starts on a 64 byte boundary after the version 1.0 preamble.
+        let header_len = usize::from(u16::from_le_bytes([bytes[8], bytes[9]]));
+        assert_eq!((10 + header_len) % 64, 0);
+
+        assert_eq!((array.rows, array.cols), (2, 3));
+        assert!(array.values[2].is_nan());
+        let expected = [-90., -45.5, f64::NAN, 0., 30.25, 89.];
+        for (value, expected) in array.values.iter().zip(expected) {
+            if !expected.is_nan() {
+                assert_eq!(*value, expected);
+            }
+        }
+    }
 }
@@ -0,0 +1,23 @@
+use crate::cli::ParamsOverrides;
+use crate::params::Params;
+use anyhow::Result;
+use std::path::PathBuf;
+
+pub fn run(params: &Option<PathBuf>, overrides: &ParamsOverrides) -> Result<()> {
+    let params = Params::load(params, overrides)?;
+
+    // Validate the position the same way simulate does.
+    params.wgs84()?;
+    let solar_position = params.solar_position()?;
+    let elevation_deg = 90. - solar_position.zenith_angle;
+
+    println!("time:      {}", params.time().to_rfc3339());
+    println!("azimuth:   {:.4} deg", solar_position.azimuth);
+    println!("elevation: {elevation_deg:.4} deg");
+
+    if elevation_deg < 0. {
+        eprintln!("warning: the sun is below the horizon");
+    }
+
+    Ok(())
+}
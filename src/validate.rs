@@ -0,0 +1,14 @@
+use crate::params::parse_params;
+use anyhow::Context;
+use anyhow::Result;
+use std::path::PathBuf;
+
+pub fn run(path: &PathBuf) -> Result<()> {
+    parse_params(path)
+        .and_then(|params| params.validate())
+        .with_context(|| format!("invalid params in {}", path.display()))?;
+
+    println!("{} is valid", path.display());
+
+    Ok(())
+}